@@ -0,0 +1,87 @@
+//! Time the hot paths of the renderer, so that changes meant to speed it up
+//! can show that they do. Run with `cargo bench`.
+//!
+//! This uses a plain timing loop rather than a benchmarking framework: each
+//! benchmark runs its body repeatedly for about a second and reports the
+//! mean time per run, along with a throughput where that means something.
+
+use mandelbrot::{escape_time, render_parallel, render_pixels, shade, Formula, Shading};
+use num::Complex;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Run `body` repeatedly for about a second, after a brief warm-up, and
+/// return the mean time each run took.
+fn time<F: FnMut()>(mut body: F) -> Duration {
+    for _ in 0..3 {
+        body();
+    }
+
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        body();
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+/// Print the mean time per run `mean` for the benchmark `name`, and, if
+/// `pixels` is given, how many pixels per second that comes to.
+fn report(name: &str, mean: Duration, pixels: Option<usize>) {
+    match pixels {
+        Some(pixels) => {
+            let rate = pixels as f64 / mean.as_secs_f64();
+            println!("{:48} {:>12.3?} {:>10.2} Mpixels/s", name, mean, rate / 1e6);
+        }
+        None => println!("{:48} {:>12.3?}", name, mean),
+    }
+}
+
+fn main() {
+    // A point that escapes after a few dozen iterations, and one in the
+    // period-3 bulb, which never escapes and which the main cardioid and
+    // period-2 bulb tests don't catch.
+    let outside = Complex { re: -0.75, im: 0.1 };
+    let inside = Complex { re: -0.12, im: 0.75 };
+
+    for &limit in &[255, 10_000] {
+        for &skip_interior in &[true, false] {
+            let formula = Formula { limit, skip_interior, ..Formula::default() };
+            for &(label, point) in &[("outside", outside), ("inside", inside)] {
+                let name = format!("escape_time {}, limit {}{}", label, limit,
+                                   if skip_interior { "" } else { ", no skipping" });
+                let mean = time(|| {
+                    black_box(escape_time(black_box(point), &formula));
+                });
+                report(&name, mean, None);
+            }
+        }
+    }
+
+    // A small render of the view from the README, which has a good mix of
+    // escaping points and points in the set.
+    let bounds = (400, 300);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+    let pixel_count = bounds.0 * bounds.1;
+    let formula = Formula::default();
+
+    let mean = time(|| {
+        black_box(render_parallel(bounds, upper_left, lower_right, &formula));
+    });
+    report("render_parallel 400x300", mean, Some(pixel_count));
+
+    let escapes = render_parallel(bounds, upper_left, lower_right, &formula);
+    let mut pixels = vec![0; pixel_count];
+    let shading = Shading::default();
+    let mean = time(|| {
+        shade(&mut pixels, &escapes, &formula, 0.2 / 400.0, &shading);
+    });
+    report("shade 400x300", mean, Some(pixel_count));
+
+    let mean = time(|| {
+        black_box(render_pixels(bounds, upper_left, lower_right, &formula, &shading));
+    });
+    report("render_pixels 400x300", mean, Some(pixel_count));
+}
@@ -0,0 +1,4056 @@
+//! Plot the Mandelbrot set and its relatives.
+//!
+//! The simplest entry point is `render_pixels`, which takes the image size,
+//! the points on the complex plane at its corners, a `Formula` saying how to
+//! iterate each point, and a `Shading` saying how to turn the results into
+//! gray levels, and returns the image's pixels, which `write_image` can save
+//! as a PNG, JPEG, GIF, or Netpbm file. For more control, `render_parallel`
+//! returns the raw `Escape` results for each pixel, which `shade` then turns
+//! into pixels.
+
+#![warn(rust_2018_idioms)]
+#![forbid(unsafe_code)]
+#![allow(elided_lifetimes_in_paths)]
+
+use num::Complex;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// How to iterate points to decide whether they are in the set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Formula {
+    /// The number of iterations to try before deciding a point is in the set.
+    pub limit: usize,
+
+    /// The exponent `d` in the iteration `z = z^d + c`. Two gives the
+    /// Mandelbrot set; higher powers give the "multibrot" sets, which have
+    /// `d - 1`-fold symmetry.
+    pub power: u32,
+
+    /// Which family of fractals to draw.
+    pub fractal: Fractal,
+
+    /// Whether to estimate each escaping point's distance from the set. This
+    /// means tracking the derivative of `z` along the orbit, which costs
+    /// time, so we only do it when asked.
+    pub estimate_distance: bool,
+
+    /// Whether to find the direction of each escaping point's surface normal,
+    /// treating the escape potential as a height field. Like distance
+    /// estimation, this means tracking the derivative of `z`.
+    pub estimate_normal: bool,
+
+    /// Whether to take shortcuts for points that are in the set: recognizing
+    /// points in the main cardioid and period-2 bulb without iterating them
+    /// at all, and stopping early when an orbit settles into a cycle. This
+    /// saves a great deal of time inside the set, but the last `z` is then
+    /// wherever we stopped, not where the orbit would be at the limit.
+    pub skip_interior: bool,
+
+    /// How much margin to leave when recognizing points in the main cardioid
+    /// and period-2 bulb with `skip_interior`. Positive values shrink the
+    /// regions recognized, so that points near their edges, which rounding
+    /// could put on the wrong side, are iterated instead; negative values
+    /// grow them. See `in_main_bulbs`.
+    pub bulb_epsilon: f64,
+
+    /// If set, track how close each orbit comes to this trap.
+    pub trap: Option<Trap>,
+
+    /// How far from the origin an orbit must get for its point to count as
+    /// having escaped. Once an orbit leaves the circle of radius two, it is
+    /// sure to escape, so that is the smallest useful radius; larger ones
+    /// take a few more iterations, but leave the escaping `z` further out,
+    /// where its size says more precisely how fast it is escaping.
+    pub bailout: f64,
+}
+
+impl Default for Formula {
+    fn default() -> Formula {
+        Formula {
+            limit: 255,
+            power: 2,
+            fractal: Fractal::Mandelbrot,
+            estimate_distance: false,
+            estimate_normal: false,
+            skip_interior: true,
+            bulb_epsilon: 0.0,
+            trap: None,
+            bailout: 2.0,
+        }
+    }
+}
+
+/// Try to determine if `c` is in the Mandelbrot set, using at most
+/// `formula.limit` iterations to decide.
+///
+/// If `c` is not a member, return `Some(i)`, where `i` is the number of
+/// iterations it took for `c` to leave the circle of radius `formula.bailout`
+/// (by default, two) centered on the origin. If `c` seems to be a member
+/// (more precisely, if we reached the iteration limit without being able to
+/// prove that `c` is not a member), return `None`.
+pub fn escape_time(c: Complex<f64>, formula: &Formula) -> Option<usize> {
+    iterate(c, formula).count
+}
+
+/// What we learned about a point by iterating it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Escape {
+    /// The point's escape time, as returned by `escape_time`.
+    pub count: Option<usize>,
+
+    /// The last value of `z` computed. For points that seem to be members of
+    /// the set, this varies smoothly across each component of the interior,
+    /// which makes it useful for shading pixels that would otherwise be solid
+    /// black.
+    pub z: Complex<f64>,
+
+    /// If `formula.estimate_distance` was set and the point escaped, an
+    /// estimate of its distance from the set, in the same units as the point
+    /// itself. Otherwise, zero.
+    pub distance: f64,
+
+    /// If `formula.estimate_normal` was set and the point escaped, a unit
+    /// vector pointing away from the set, along the steepest rise of the
+    /// escape potential. Otherwise, zero.
+    pub normal: Complex<f64>,
+
+    /// If `formula.trap` was set, the closest the orbit came to the trap
+    /// before escaping, or before we stopped iterating it. Otherwise, zero.
+    pub trap_distance: f64,
+}
+
+/// Iterate `c` exactly as `escape_time` does, but return everything we learn
+/// along the way, not just the escape time.
+pub fn iterate(c: Complex<f64>, formula: &Formula) -> Escape {
+    // Choose the function to apply to each `z` here, rather than inside the
+    // loop, so that the compiler can generate a tight loop for each.
+    match (formula.fractal, formula.power) {
+        (Fractal::Mandelbrot, 2)
+            if formula.skip_interior && in_main_bulbs(c, formula.bulb_epsilon) =>
+            Escape { count: None, ..Escape::default() },
+        (Fractal::Mandelbrot, 2) => iterate_with(c, formula, |z| z * z),
+        (Fractal::Mandelbrot, power) => iterate_with(c, formula, |z| z.powu(power)),
+        (Fractal::BurningShip, power) => iterate_with(c, formula, |z| {
+            Complex { re: z.re.abs(), im: z.im.abs() }.powu(power)
+        }),
+    }
+}
+
+/// Return true if `c` is in the Mandelbrot set's main cardioid or its
+/// period-2 bulb, the disk of radius 1/4 centered on -1. Between them, these
+/// cover most of the set's area, and we can recognize their points directly.
+///
+/// Each test compares two quantities that are equal on the region's edge.
+/// `epsilon` is subtracted from the side that bounds the region, so that
+/// points must be that far inside to count; zero gives the exact tests.
+fn in_main_bulbs(c: Complex<f64>, epsilon: f64) -> bool {
+    let x = c.re - 0.25;
+    let y2 = c.im * c.im;
+    let q = x * x + y2;
+    q * (q + x) <= y2 / 4.0 - epsilon
+        || (c.re + 1.0) * (c.re + 1.0) + y2 <= 1.0 / 16.0 - epsilon
+}
+
+#[test]
+fn test_in_main_bulbs() {
+    let inside = |re, im| in_main_bulbs(Complex { re, im }, 0.0);
+
+    // The cardioid reaches from its cusp at 1/4 along the real axis to -3/4,
+    // where the bulb takes over and continues to -5/4.
+    assert!(inside(0.2499, 0.0) && !inside(0.2501, 0.0));
+    assert!(inside(-0.7499, 0.0) && inside(-0.7501, 0.0));
+    assert!(inside(-1.2499, 0.0) && !inside(-1.2501, 0.0));
+
+    // The cardioid's boundary passes through 1/4 ± i/2, and the bulb's top
+    // is at -1 + i/4. Where they meet, the gap between them isn't covered.
+    assert!(inside(0.25, 0.499) && !inside(0.25, 0.501));
+    assert!(inside(-1.0, -0.2499) && !inside(-1.0, -0.2501));
+    assert!(!inside(-0.75, 0.001));
+
+    // Everything it recognizes really is in the set.
+    let full = Formula { skip_interior: false, ..Formula::default() };
+    for row in 0..60 {
+        for column in 0..80 {
+            let c = pixel_to_point((80, 60), (column, row),
+                                   Complex { re: -2.0, im: 1.2 },
+                                   Complex { re: 0.6, im: -1.2 });
+            if in_main_bulbs(c, 0.0) {
+                assert_eq!(escape_time(c, &full), None);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_in_main_bulbs_epsilon() {
+    // Points a millionth inside and outside the bulb's edge, and the
+    // cardioid's at its top.
+    let bulb_inside = Complex { re: -1.249999, im: 0.0 };
+    let bulb_outside = Complex { re: -1.250001, im: 0.0 };
+    let cardioid_inside = Complex { re: 0.25, im: 0.499999 };
+    let cardioid_outside = Complex { re: 0.25, im: 0.500001 };
+    for &(inside, outside) in &[(bulb_inside, bulb_outside),
+                                (cardioid_inside, cardioid_outside)] {
+        assert!(in_main_bulbs(inside, 0.0));
+        assert!(!in_main_bulbs(outside, 0.0));
+
+        // A margin wider than the gap leaves the point inside to be
+        // iterated; a negative one takes in the point outside.
+        assert!(!in_main_bulbs(inside, 1e-5));
+        assert!(in_main_bulbs(outside, -1e-5));
+
+        // A margin much narrower than the gap changes nothing.
+        assert!(in_main_bulbs(inside, 1e-9) && !in_main_bulbs(outside, -1e-9));
+    }
+
+    // A point the test no longer recognizes is still found to be in the set,
+    // just by iterating.
+    let formula = Formula { bulb_epsilon: 1e-5, limit: 2000, ..Formula::default() };
+    assert_eq!(escape_time(bulb_inside, &formula), None);
+}
+
+/// Iterate `c` as `iterate` does, computing the next value of `z` as
+/// `raise(z) + c`.
+#[inline(always)]
+fn iterate_with<F>(c: Complex<f64>, formula: &Formula, raise: F) -> Escape
+    where F: Fn(Complex<f64>) -> Complex<f64>
+{
+    // Passing `check_periodicity` and `trap` as constants lets the compiler
+    // leave them out of the loop entirely when they're not wanted.
+    match (formula.skip_interior, formula.trap) {
+        (true, None) => iterate_loop(c, formula, raise, true, None),
+        (false, None) => iterate_loop(c, formula, raise, false, None),
+        (true, Some(Trap::Point)) => iterate_loop(c, formula, raise, true, Some(Trap::Point)),
+        (true, Some(Trap::Cross)) => iterate_loop(c, formula, raise, true, Some(Trap::Cross)),
+        (false, trap) => iterate_loop(c, formula, raise, false, trap),
+    }
+}
+
+/// Iterate `c` as `iterate_with` does, checking for cycles only if
+/// `check_periodicity` is true, and tracking the orbit's distance from
+/// `trap`, if given.
+#[inline(always)]
+fn iterate_loop<F>(c: Complex<f64>,
+                   formula: &Formula,
+                   raise: F,
+                   check_periodicity: bool,
+                   trap: Option<Trap>)
+    -> Escape
+    where F: Fn(Complex<f64>) -> Complex<f64>
+{
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    let mut dz = Complex { re: 0.0, im: 0.0 };
+    let mut trap_distance = if trap.is_some() { f64::INFINITY } else { 0.0 };
+
+    // To detect cycles, we compare `z` with a value we saved earlier, saving
+    // a fresh one whenever the iteration count reaches a power of two. To
+    // keep the loop fast, we only compare on every eighth iteration, so a
+    // cycle of period `p` shows up once the gap since the last save reaches
+    // a multiple of both eight and `p`.
+    let mut saved = z;
+    let mut next_save = 8;
+
+    let bailout_sqr = formula.bailout * formula.bailout;
+    for i in 0..formula.limit {
+        if z.norm_sqr() > bailout_sqr {
+            let mut escape = Escape { count: Some(i), z, trap_distance, ..Escape::default() };
+            if formula.estimate_distance || formula.estimate_normal {
+                let (far_z, far_dz) = escape_further(c, z, dz, formula, raise);
+                if formula.estimate_distance {
+                    let norm = far_z.norm();
+                    escape.distance = norm * norm.ln() / far_dz.norm();
+                }
+                if formula.estimate_normal {
+                    let u = far_z / far_dz;
+                    escape.normal = u / u.norm();
+                }
+            }
+            return escape;
+        }
+        if formula.estimate_distance || formula.estimate_normal {
+            dz = derivative_step(z, dz, formula);
+        }
+        z = raise(z) + c;
+        if let Some(trap) = trap {
+            trap_distance = trap_distance.min(trap.distance(z));
+        }
+
+        if check_periodicity && i % 8 == 0 {
+            if (z - saved).norm_sqr() < PERIODICITY_EPSILON * PERIODICITY_EPSILON {
+                return Escape { count: None, z, trap_distance, ..Escape::default() };
+            }
+            if i == next_save {
+                saved = z;
+                next_save *= 2;
+            }
+        }
+    }
+
+    Escape { count: None, z, trap_distance, ..Escape::default() }
+}
+
+/// How close an orbit must come to a point it visited earlier for us to
+/// decide that it has settled into a cycle. Orbits drawn to an attracting
+/// cycle soon approach it as closely as `f64` can represent, so this is only
+/// a few hundred ulps for values of `z` near one.
+const PERIODICITY_EPSILON: f64 = 1e-13;
+
+#[test]
+fn test_periodicity() {
+    // Points in the main cardioid, the period-2 bulb, and the period-3 bulbs
+    // are caught long before any realistic iteration limit.
+    let formula = Formula { limit: usize::MAX, ..Formula::default() };
+    for &(re, im) in &[(-0.5, 0.0), (0.0, 0.0), (-1.0, 0.0),
+                       (-0.122, 0.745), (-1.755, 0.0)] {
+        assert_eq!(escape_time(Complex { re, im }, &formula), None);
+    }
+
+    // Checking never changes an escape count.
+    let full = Formula { skip_interior: false, ..Formula::default() };
+    for row in 0..60 {
+        for column in 0..80 {
+            let c = pixel_to_point((80, 60), (column, row),
+                                   Complex { re: -2.0, im: 1.2 },
+                                   Complex { re: 0.6, im: -1.2 });
+            assert_eq!(escape_time(c, &Formula::default()), escape_time(c, &full));
+        }
+    }
+}
+
+/// How many points `iterate_lanes` iterates side by side.
+const LANES: usize = 4;
+
+/// Iterate each of the points `c` exactly as `iterate` does, returning the
+/// same results.
+///
+/// For the plain Mandelbrot set, we step all the orbits together, each in
+/// its own lane, carrying on until every one has escaped or been found to
+/// be in the set. Written this way, with the real and imaginary parts in
+/// separate arrays, the compiler can use SIMD instructions to advance
+/// several orbits at once. A lane whose orbit is finished keeps iterating
+/// with the rest, but its result is already recorded, so that doesn't
+/// matter. Other formulas just iterate each point in turn.
+fn iterate_lanes(c: [Complex<f64>; LANES], formula: &Formula) -> [Escape; LANES] {
+    let plain = formula.fractal == Fractal::Mandelbrot && formula.power == 2
+        && !formula.estimate_distance && !formula.estimate_normal
+        && formula.trap.is_none();
+    match (plain, formula.skip_interior) {
+        (true, true) => iterate_lanes_loop(c, formula, true),
+        (true, false) => iterate_lanes_loop(c, formula, false),
+        (false, _) => {
+            let mut escapes = [Escape::default(); LANES];
+            for (escape, &c) in escapes.iter_mut().zip(&c) {
+                *escape = iterate(c, formula);
+            }
+            escapes
+        }
+    }
+}
+
+/// Iterate the plain Mandelbrot set's points `c` side by side, as
+/// `iterate_lanes` describes, checking for cycles and recognizing points in
+/// the main bulbs only if `check_periodicity` is true, as `iterate_loop`
+/// does.
+///
+/// Each step must do exactly the same floating-point operations, in the same
+/// order, as `iterate_loop`'s `z * z + c`, so that the results agree to the
+/// last bit.
+#[inline(always)]
+fn iterate_lanes_loop(c: [Complex<f64>; LANES], formula: &Formula, check_periodicity: bool)
+    -> [Escape; LANES]
+{
+    let mut escapes = [Escape::default(); LANES];
+    let mut done = [false; LANES];
+    if check_periodicity {
+        for (done, &c) in done.iter_mut().zip(&c) {
+            *done = in_main_bulbs(c, formula.bulb_epsilon);
+        }
+    }
+
+    let (mut re, mut im) = ([0.0; LANES], [0.0; LANES]);
+    let (mut saved_re, mut saved_im) = (re, im);
+    let mut next_save = 8;
+
+    let bailout_sqr = formula.bailout * formula.bailout;
+    for i in 0..formula.limit {
+        for k in 0..LANES {
+            if !done[k] && re[k] * re[k] + im[k] * im[k] > bailout_sqr {
+                let z = Complex { re: re[k], im: im[k] };
+                escapes[k] = Escape { count: Some(i), z, ..Escape::default() };
+                done[k] = true;
+            }
+        }
+        if done.iter().all(|&done| done) {
+            return escapes;
+        }
+
+        for k in 0..LANES {
+            let (z_re, z_im) = (re[k], im[k]);
+            re[k] = z_re * z_re - z_im * z_im + c[k].re;
+            im[k] = z_re * z_im + z_im * z_re + c[k].im;
+        }
+
+        if check_periodicity && i % 8 == 0 {
+            for k in 0..LANES {
+                let (d_re, d_im) = (re[k] - saved_re[k], im[k] - saved_im[k]);
+                if !done[k]
+                    && d_re * d_re + d_im * d_im < PERIODICITY_EPSILON * PERIODICITY_EPSILON
+                {
+                    let z = Complex { re: re[k], im: im[k] };
+                    escapes[k] = Escape { count: None, z, ..Escape::default() };
+                    done[k] = true;
+                }
+            }
+            if i == next_save {
+                saved_re = re;
+                saved_im = im;
+                next_save *= 2;
+            }
+        }
+    }
+
+    for k in 0..LANES {
+        if !done[k] {
+            escapes[k] = Escape { count: None, z: Complex { re: re[k], im: im[k] },
+                                  ..Escape::default() };
+        }
+    }
+    escapes
+}
+
+#[test]
+fn test_iterate_lanes() {
+    // Points all over the set and its surroundings: inside and outside the
+    // main bulbs, in smaller bulbs where periodicity checking stops them, and
+    // near the boundary, where they take long to escape.
+    let points: Vec<Complex<f64>> = (0..97 * 61)
+        .map(|i| pixel_to_point((97, 61), (i % 97, i / 97),
+                                Complex { re: -2.1, im: 1.2 },
+                                Complex { re: 0.6, im: -1.2 }))
+        .collect();
+    let formulas = [
+        Formula::default(),
+        Formula { skip_interior: false, ..Formula::default() },
+        Formula { limit: 1000, ..Formula::default() },
+        Formula { bailout: 100.0, ..Formula::default() },
+        Formula { power: 3, ..Formula::default() },
+        Formula { estimate_distance: true, ..Formula::default() },
+    ];
+    for formula in &formulas {
+        for chunk in points.chunks_exact(LANES) {
+            let mut c = [Complex::default(); LANES];
+            c.copy_from_slice(chunk);
+            let expected: Vec<Escape> = c.iter().map(|&c| iterate(c, formula)).collect();
+            assert_eq!(iterate_lanes(c, formula)[..], expected[..], "{:?}", formula);
+        }
+    }
+}
+
+/// Given the derivative `dz` of `z` with respect to `c`, return the derivative
+/// of the value that follows `z` in the orbit: for `z = z^d + c`, that's
+/// `d·z^(d-1)·dz + 1`. The Burning Ship's absolute values make its orbits
+/// non-differentiable, so for it this is only a rough approximation.
+fn derivative_step(z: Complex<f64>, dz: Complex<f64>, formula: &Formula) -> Complex<f64> {
+    let power = formula.power;
+    z.powu(power - 1) * dz * power as f64 + 1.0
+}
+
+/// Given that the orbit of `c` has escaped to `z`, and that the derivative of
+/// `z` with respect to `c` is `dz`, continue the orbit until `z` is far from
+/// the origin, and return the final `z` and `dz`. The arguments `formula` and
+/// `raise` are as for `iterate_with`.
+///
+/// We use these to estimate the distance from `c` to the set, as
+/// `|z|·ln|z| / |dz|`, and the direction away from it, as `z / dz`. Both are
+/// good approximations only when `z` is large, and escaping the circle of
+/// radius two isn't nearly large enough for points that escape quickly. The
+/// escape count is already settled, so this doesn't affect it.
+fn escape_further<F>(c: Complex<f64>,
+                     mut z: Complex<f64>,
+                     mut dz: Complex<f64>,
+                     formula: &Formula,
+                     raise: F)
+    -> (Complex<f64>, Complex<f64>)
+    where F: Fn(Complex<f64>) -> Complex<f64>
+{
+    while z.norm_sqr() < 1e6 {
+        dz = derivative_step(z, dz, formula);
+        z = raise(z) + c;
+    }
+    (z, dz)
+}
+
+#[test]
+fn test_estimate_distance() {
+    let formula = Formula { estimate_distance: true, ..Formula::default() };
+
+    // The set reaches exactly to -2 on the negative real axis, so the true
+    // distance of points beyond that is easy to compute.
+    for &(re, expected) in &[(-2.01, 0.01), (-2.1, 0.1), (-2.5, 0.5), (-3.0, 1.0)] {
+        let distance = iterate(Complex { re, im: 0.0 }, &formula).distance;
+        assert!(expected < distance && distance < 2.5 * expected,
+                "estimated distance of {} from set as {}", re, distance);
+    }
+
+    // Estimating distance doesn't change anything else.
+    let c = Complex { re: -0.75, im: 0.1 };
+    assert_eq!(iterate(c, &formula).count, escape_time(c, &Formula::default()));
+    assert_eq!(iterate(c, &Formula::default()).distance, 0.0);
+    assert_eq!(iterate(Complex { re: 0.0, im: 0.0 }, &formula).distance, 0.0);
+}
+
+#[test]
+fn test_estimate_normal() {
+    let formula = Formula { estimate_normal: true, ..Formula::default() };
+
+    // Along the real axis, beyond either end of the set, the normal points
+    // straight outwards.
+    let normal = iterate(Complex { re: -2.5, im: 0.0 }, &formula).normal;
+    assert!((normal - Complex { re: -1.0, im: 0.0 }).norm() < 1e-9);
+    let normal = iterate(Complex { re: 1.0, im: 0.0 }, &formula).normal;
+    assert!((normal - Complex { re: 1.0, im: 0.0 }).norm() < 1e-9);
+
+    // Far from the set, it points away from the origin.
+    let c = Complex { re: 10.0, im: 10.0 };
+    let normal = iterate(c, &formula).normal;
+    assert!((normal.norm() - 1.0).abs() < 1e-9);
+    assert!((normal - c / c.norm()).norm() < 0.1);
+
+    // Estimating the normal doesn't change anything else.
+    let c = Complex { re: -0.75, im: 0.1 };
+    assert_eq!(iterate(c, &formula).count, escape_time(c, &Formula::default()));
+    assert_eq!(iterate(c, &Formula::default()).normal, Complex { re: 0.0, im: 0.0 });
+    assert_eq!(iterate(Complex { re: 0.0, im: 0.0 }, &formula).normal,
+               Complex { re: 0.0, im: 0.0 });
+}
+
+#[test]
+fn test_iterate_power() {
+    // Every multibrot contains the origin, and nothing outside the circle of
+    // radius two.
+    for power in 2..6 {
+        let formula = Formula { power, ..Formula::default() };
+        assert_eq!(escape_time(Complex { re: 0.0, im: 0.0 }, &formula), None);
+        assert!(escape_time(Complex { re: 2.1, im: 0.0 }, &formula).is_some());
+    }
+
+    // The power-3 set has two lobes, reaching out along the imaginary axis
+    // to about ±1.1i, and staying within about 0.6 of it along the real axis.
+    let cubic = Formula { power: 3, ..Formula::default() };
+    assert_eq!(escape_time(Complex { re: 0.0, im: 1.0 }, &cubic), None);
+    assert!(escape_time(Complex { re: 0.0, im: 1.3 }, &cubic).is_some());
+    assert!(escape_time(Complex { re: 0.8, im: 0.0 }, &cubic).is_some());
+    assert!(escape_time(Complex { re: -0.8, im: 0.0 }, &cubic).is_some());
+
+    // The point -1 is in the Mandelbrot set's period-2 bulb, but not in the
+    // symmetric cubic set.
+    assert_eq!(escape_time(Complex { re: -1.0, im: 0.0 }, &Formula::default()), None);
+    assert!(escape_time(Complex { re: -1.0, im: 0.0 }, &cubic).is_some());
+}
+
+#[test]
+fn test_iterate_burning_ship() {
+    let ship = Formula { fractal: Fractal::BurningShip, ..Formula::default() };
+
+    // The antenna off the ship's bow carries a miniature copy of the ship,
+    // just below the real axis.
+    assert_eq!(escape_time(Complex { re: -1.76, im: -0.015 }, &ship), None);
+
+    // Unlike the Mandelbrot set, the ship isn't symmetric about the real
+    // axis.
+    assert!(escape_time(Complex { re: -1.76, im: 0.015 }, &ship).is_some());
+}
+
+#[test]
+fn test_bailout() {
+    let formula = Formula::default();
+    let wide = Formula { bailout: 256.0, ..Formula::default() };
+
+    // A larger radius takes longer to leave, but never changes which points
+    // escape, and leaves `z` outside it.
+    let mut c = Complex { re: -2.2, im: -1.3 };
+    while c.re < 1.0 {
+        let (near, far) = (iterate(c, &formula), iterate(c, &wide));
+        assert_eq!(near.count.is_some(), far.count.is_some(), "at {}", c);
+        if let (Some(near_count), Some(far_count)) = (near.count, far.count) {
+            assert!(far_count >= near_count);
+            assert!(far.z.norm() > 256.0);
+        }
+        c += Complex { re: 0.0173, im: 0.0131 };
+    }
+
+    // The orbit of 1 goes 0, 1, 2, 5, 26, 677, so it has left the circle of
+    // radius two after three iterations, but needs five to leave one of 256.
+    let c = Complex { re: 1.0, im: 0.0 };
+    assert_eq!(escape_time(c, &formula), Some(3));
+    assert_eq!(escape_time(c, &wide), Some(5));
+}
+
+/// Return a smoothed escape count for a point that escaped after `count`
+/// iterations under `formula`, with its orbit landing at `z`.
+///
+/// Whole escape counts jump from one to the next where an orbit's last step
+/// just crosses the bailout radius `r`. But the further past `r` the orbit
+/// lands, the sooner it would have escaped a slightly smaller circle, and
+/// each step raises `|z|` to the power `d` about, so
+/// `count + 1 - log_d(ln |z| / ln r)` runs smoothly from `count + 1`, just
+/// outside the radius, down to `count`, where the previous count takes over.
+/// The larger the radius, the more exact this is.
+pub fn smooth_count(count: usize, z: Complex<f64>, formula: &Formula) -> f64 {
+    let ratio = z.norm().ln() / formula.bailout.ln();
+    count as f64 + 1.0 - ratio.ln() / (formula.power as f64).ln()
+}
+
+#[test]
+fn test_smooth_count() {
+    // Sample a line of points running away from the set, from near the cusp
+    // of the main cardioid out along the real axis.
+    let column = |formula: &Formula| -> Vec<(usize, f64)> {
+        (0..1000)
+            .map(|i| Complex { re: 0.3 + i as f64 * 0.0007, im: 0.0 })
+            .map(|c| {
+                let escape = iterate(c, formula);
+                let count = escape.count.unwrap();
+                (count, smooth_count(count, escape.z, formula))
+            })
+            .collect()
+    };
+    let largest_jump = |values: &[f64]| {
+        values.windows(2).map(|w| (w[0] - w[1]).abs()).fold(0.0, f64::max)
+    };
+
+    for &bailout in &[2.0, 256.0] {
+        let formula = Formula { bailout, ..Formula::default() };
+        let samples = column(&formula);
+
+        // The smoothed count stays within a step of the whole one. The `+ c`
+        // can carry the last step a little further out than `r^d`, so it may
+        // fall a little short of `count`.
+        for &(count, smooth) in &samples {
+            assert!(count as f64 - 0.25 < smooth && smooth <= count as f64 + 1.0,
+                    "count {} smoothed to {}", count, smooth);
+        }
+
+        // Whole counts change in steps of one, but the smoothed count moves
+        // in much smaller steps, and more so with a larger radius.
+        let counts: Vec<f64> = samples.iter().map(|&(count, _)| count as f64).collect();
+        let smooth: Vec<f64> = samples.iter().map(|&(_, smooth)| smooth).collect();
+        assert_eq!(largest_jump(&counts), 1.0);
+        let limit = if bailout == 2.0 { 0.5 } else { 0.25 };
+        assert!(largest_jump(&smooth) < limit,
+                "largest jump with bailout {}: {}", bailout, largest_jump(&smooth));
+    }
+}
+
+#[test]
+fn test_shade_smooth() {
+    let bounds = (60, 40);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let formula = Formula { bailout: 100.0, ..Formula::default() };
+
+    // Cycle through the grays every sixteen counts, so that each count gets
+    // about sixteen gray levels to itself.
+    let discrete = Shading { modulo: Some(16), cycle: CycleMode::Triangle,
+                             ..Shading::default() };
+    let smooth = Shading { color_mode: ColorMode::Smooth, ..discrete };
+    let discrete = render_pixels(bounds, upper_left, lower_right, &formula, &discrete);
+    let smooth = render_pixels(bounds, upper_left, lower_right, &formula, &smooth);
+
+    // The interior is black either way, and smoothing moves each escaping
+    // pixel by little more than one count's worth of gray.
+    for (&d, &s) in discrete.iter().zip(&smooth) {
+        assert_eq!(d == 0, s == 0);
+        assert!((d as i32 - s as i32).abs() <= 20, "{} became {}", d, s);
+    }
+
+    // But where whole counts give only seventeen grays, plus black for the
+    // interior, smoothed counts fill in the range between them.
+    let levels = |pixels: &[u8]| {
+        let mut seen = [false; 256];
+        for &p in pixels {
+            seen[p as usize] = true;
+        }
+        seen.iter().filter(|&&seen| seen).count()
+    };
+    assert!(levels(&discrete) <= 18);
+    assert!(levels(&smooth) > 4 * levels(&discrete));
+}
+
+#[test]
+fn test_iterate_power_two_unchanged() {
+    // Raising to the power two with `powu` squares exactly as `z * z` does,
+    // so the generalized loop must agree bit for bit with the original one.
+    // Periodicity checking would stop some orbits early, so leave it off.
+    let formula = Formula { skip_interior: false, ..Formula::default() };
+    let mut c = Complex { re: -1.5, im: -1.0 };
+    while c.im < 1.0 {
+        let mut z = Complex { re: 0.0, im: 0.0 };
+        let mut expected = None;
+        for i in 0..255 {
+            if z.norm_sqr() > 4.0 {
+                expected = Some(i);
+                break;
+            }
+            z = z * z + c;
+        }
+
+        let escape = iterate(c, &formula);
+        assert_eq!(escape.count, expected);
+        assert_eq!(escape.z, z);
+        assert_eq!(z.powu(2), z * z);
+        c += Complex { re: 0.0137, im: 0.0071 };
+    }
+}
+
+use std::str::FromStr;
+
+/// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0,0.5"`.
+///
+/// Specifically, `s` should have the form <left><sep><right>, where <sep> is
+/// the character given by the `separator` argument, and <left> and <right> are both
+/// strings that can be parsed by `T::from_str`.
+///
+/// If `s` has the proper form, return `Some<(x, y)>`. If it doesn't parse
+/// correctly, return `None`.
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
+    match s.find(separator) {
+        None => None,
+        Some(index) => {
+            match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
+                (Ok(l), Ok(r)) => Some((l, r)),
+                _ => None
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_pair() {
+    assert_eq!(parse_pair::<i32>("",        ','), None);
+    assert_eq!(parse_pair::<i32>("10,",     ','), None);
+    assert_eq!(parse_pair::<i32>(",10",     ','), None);
+    assert_eq!(parse_pair::<i32>("10,20",   ','), Some((10, 20)));
+    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
+    assert_eq!(parse_pair::<f64>("0.5x",    'x'), None);
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+}
+
+/// Parse a complex number, written either as a pair of floating-point numbers
+/// separated by a comma, like `"-0.75,0.1"`, or in the usual mathematical
+/// notation, like `"-0.75+0.1i"`, `"0.1i"`, or `"-0.75"`, as other fractal
+/// programs print them.
+pub fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    if s.contains(',') {
+        return parse_pair(s, ',').map(|(re, im)| Complex { re, im });
+    }
+
+    let imaginary = match s.strip_suffix('i') {
+        None => return f64::from_str(s).ok().map(|re| Complex { re, im: 0.0 }),
+        Some(imaginary) => imaginary
+    };
+
+    // The imaginary part starts at the last sign that isn't the string's
+    // first character or part of an exponent, like the one in `1e-3`. If
+    // there's no such sign, there's no real part.
+    let bytes = imaginary.as_bytes();
+    let split = (1..bytes.len()).rev()
+        .find(|&i| (bytes[i] == b'+' || bytes[i] == b'-')
+                   && bytes[i - 1] != b'e' && bytes[i - 1] != b'E');
+    let (re, im) = match split {
+        Some(index) => (f64::from_str(&imaginary[..index]).ok()?,
+                        f64::from_str(&imaginary[index..]).ok()?),
+        None => (0.0, f64::from_str(imaginary).ok()?)
+    };
+    Some(Complex { re, im })
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(parse_complex("1.25,-0.0625"),
+               Some(Complex { re: 1.25, im: -0.0625 }));
+    assert_eq!(parse_complex(",-0.0625"), None);
+
+    assert_eq!(parse_complex("-0.743+0.126i"),
+               Some(Complex { re: -0.743, im: 0.126 }));
+    assert_eq!(parse_complex("0.25-1.5i"), Some(Complex { re: 0.25, im: -1.5 }));
+    assert_eq!(parse_complex("+1+2i"), Some(Complex { re: 1.0, im: 2.0 }));
+    assert_eq!(parse_complex("1e-3-2E+2i"), Some(Complex { re: 0.001, im: -200.0 }));
+    assert_eq!(parse_complex("-0.5"), Some(Complex { re: -0.5, im: 0.0 }));
+    assert_eq!(parse_complex("+0.5"), Some(Complex { re: 0.5, im: 0.0 }));
+    assert_eq!(parse_complex("0.5i"), Some(Complex { re: 0.0, im: 0.5 }));
+    assert_eq!(parse_complex("-2e-3i"), Some(Complex { re: 0.0, im: -0.002 }));
+    assert_eq!(parse_complex(""), None);
+    assert_eq!(parse_complex("i"), None);
+    assert_eq!(parse_complex("1+i"), None);
+    assert_eq!(parse_complex("1+2j"), None);
+    assert_eq!(parse_complex("1+2+3i"), None);
+    assert_eq!(parse_complex("x+2i"), None);
+}
+
+/// Given the row and column of a pixel in the output image, return the
+/// corresponding point on the complex plane.
+///
+/// `bounds` is a pair giving the width and height of the image in pixels.
+/// `pixel` is a (column, row) pair indicating a particular pixel in that image.
+/// The `upper_left` and `lower_right` parameters are points on the complex
+/// plane designating the area our image covers.
+pub fn pixel_to_point(bounds: (usize, usize),
+                      pixel: (usize, usize),
+                      upper_left: Complex<f64>,
+                      lower_right: Complex<f64>)
+    -> Complex<f64>
+{
+    let (width, height) = (lower_right.re - upper_left.re,
+                           upper_left.im - lower_right.im);
+    Complex {
+        re: upper_left.re + pixel.0 as f64 * width  / bounds.0 as f64,
+        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
+        // Why subtraction here? pixel.1 increases as we go down,
+        // but the imaginary component increases as we go up.
+    }
+}
+
+#[test]
+fn test_pixel_to_point() {
+    assert_eq!(pixel_to_point((100, 200), (25, 175),
+                              Complex { re: -1.0, im:  1.0 },
+                              Complex { re:  1.0, im: -1.0 }),
+               Complex { re: -0.5, im: -0.75 });
+}
+
+/// A named view of an interesting part of the set.
+pub struct Location {
+    /// The name to ask for the view by.
+    pub name: &'static str,
+
+    /// The point at the center of the view.
+    pub center: Complex<f64>,
+
+    /// The width of the view on the complex plane. Its height follows from
+    /// the image's aspect ratio.
+    pub width: f64,
+
+    /// An iteration limit high enough to bring out the view's detail.
+    pub limit: usize,
+}
+
+/// Some well-known views of the set, to be looked up by name.
+pub const LOCATIONS: &[Location] = &[
+    Location {
+        name: "seahorse-valley",
+        center: Complex { re: -0.7453, im: 0.1127 },
+        width: 0.0065,
+        limit: 1000,
+    },
+    Location {
+        name: "elephant-valley",
+        center: Complex { re: 0.2850, im: 0.0110 },
+        width: 0.01,
+        limit: 1000,
+    },
+    Location {
+        name: "triple-spiral",
+        center: Complex { re: -0.0880, im: 0.6550 },
+        width: 0.003,
+        limit: 2000,
+    },
+    Location {
+        name: "mini-mandelbrot",
+        center: Complex { re: -1.77, im: 0.0 },
+        width: 0.06,
+        limit: 500,
+    },
+];
+
+/// Return the location named `name`, or an error message listing the
+/// locations we know if there is none.
+pub fn find_location(name: &str) -> Result<&'static Location, String> {
+    LOCATIONS.iter()
+        .find(|location| location.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = LOCATIONS.iter().map(|l| l.name).collect();
+            format!("unknown location: {} (try one of: {})", name, names.join(", "))
+        })
+}
+
+impl Location {
+    /// Return the upper left and lower right corners of this view, magnified
+    /// `zoom` times, for an image with the given `bounds` whose pixels are
+    /// `pixel_aspect` times as wide as they are tall.
+    pub fn corners(&self, bounds: (usize, usize), zoom: f64, pixel_aspect: f64)
+        -> (Complex<f64>, Complex<f64>)
+    {
+        view_corners(self.center, self.width / zoom, bounds, pixel_aspect)
+    }
+}
+
+/// The width of a view of the whole set: enough to span it from left to
+/// right when centered on -0.75.
+pub const DEFAULT_VIEW_WIDTH: f64 = 3.0;
+
+/// Return the upper left and lower right corners of a view `width` wide,
+/// centered on `center`, for an image with the given `bounds`. The view's
+/// height follows from the image's aspect ratio, so that each pixel covers
+/// `pixel_aspect` times as much of the real axis as of the imaginary axis:
+/// shown on a display whose pixels have that shape, the plane is undistorted.
+pub fn view_corners(center: Complex<f64>,
+                    width: f64,
+                    bounds: (usize, usize),
+                    pixel_aspect: f64)
+    -> (Complex<f64>, Complex<f64>)
+{
+    let height = width * bounds.1 as f64 / bounds.0 as f64 / pixel_aspect;
+    let half = Complex { re: width / 2.0, im: -height / 2.0 };
+    (center - half, center + half)
+}
+
+/// Check that an image `bounds` pixels in size, showing the complex plane
+/// from `upper_left` to `lower_right`, has pixels covering `pixel_aspect`
+/// times as much of the real axis as of the imaginary axis, returning a
+/// message suggesting a lower right corner that would make them so if not.
+/// With a `pixel_aspect` of one, that means square pixels.
+///
+/// A pixel covers `(lower_right.re - upper_left.re) / bounds.0` of the real
+/// axis and `(upper_left.im - lower_right.im) / bounds.1` of the imaginary
+/// axis; for square pixels, the view's width over its height must equal the
+/// image's width over its height. Corners typed in by hand are rarely exact,
+/// so we allow a difference of one part in a thousand, which no one will
+/// see. A `Location` computes its height from its width this way, so its
+/// views always pass.
+pub fn check_aspect(bounds: (usize, usize),
+                    upper_left: Complex<f64>,
+                    lower_right: Complex<f64>,
+                    pixel_aspect: f64)
+    -> Result<(), String>
+{
+    let pixel_width = (lower_right.re - upper_left.re) / bounds.0 as f64;
+    let pixel_height = (upper_left.im - lower_right.im) / bounds.1 as f64 * pixel_aspect;
+    if ((pixel_width - pixel_height) / pixel_width).abs() <= 1e-3 {
+        return Ok(());
+    }
+
+    let shape = if pixel_aspect == 1.0 {
+        "square".to_string()
+    } else {
+        format!("{} times as wide as they are tall", pixel_aspect)
+    };
+    let fitting_im = upper_left.im - pixel_width / pixel_aspect * bounds.1 as f64;
+    Err(format!("the pixels of a {}x{} image from {},{} to {},{} are not {}; \
+                 use a lower right corner of {},{} to make them so, or \
+                 --aspect stretch to draw the image stretched",
+                bounds.0, bounds.1, upper_left.re, upper_left.im,
+                lower_right.re, lower_right.im, shape, lower_right.re, fitting_im))
+}
+
+#[test]
+fn test_check_aspect() {
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    assert_eq!(check_aspect((300, 200), upper_left, lower_right, 1.0), Ok(()));
+    assert_eq!(check_aspect((1000, 750),
+                            Complex { re: -1.20, im: 0.35 },
+                            Complex { re: -1.0, im: 0.20 }, 1.0), Ok(()));
+
+    // Views of the well-known locations always pass.
+    let (location_upper_left, location_lower_right) =
+        LOCATIONS[0].corners((200, 100), 1.0, 1.0);
+    assert_eq!(check_aspect((200, 100), location_upper_left, location_lower_right, 1.0),
+               Ok(()));
+
+    let message = check_aspect((400, 400), upper_left, lower_right, 1.0).unwrap_err();
+    assert!(message.contains("1,-2"), "{}", message);
+    assert!(message.contains("--aspect stretch"));
+
+    // Pixels twice as wide as they are tall should cover twice as much of
+    // the real axis as of the imaginary.
+    assert_eq!(check_aspect((150, 200), upper_left, lower_right, 2.0), Ok(()));
+    let message = check_aspect((300, 200), upper_left, lower_right, 2.0).unwrap_err();
+    assert!(message.contains("1,0"), "{}", message);
+}
+
+#[test]
+fn test_locations() {
+    // Every view shows some of the set, and some of the area around it.
+    let bounds = (64, 48);
+    for location in LOCATIONS {
+        let (upper_left, lower_right) = location.corners(bounds, 1.0, 1.0);
+        assert!(upper_left.re < lower_right.re && upper_left.im > lower_right.im);
+        assert!((lower_right.re - upper_left.re - location.width).abs() < 1e-12);
+
+        let formula = Formula { limit: location.limit, ..Formula::default() };
+        let escapes = render_parallel(bounds, upper_left, lower_right, &formula);
+        assert!(escapes.iter().any(|e| e.count.is_none()), "{}", location.name);
+        assert!(escapes.iter().any(|e| e.count.is_some()), "{}", location.name);
+    }
+
+    assert_eq!(find_location("triple-spiral").unwrap().limit, 2000);
+    let message = find_location("nowhere").err().unwrap();
+    assert!(message.contains("seahorse-valley") && message.contains("mini-mandelbrot"));
+
+    // A 2:1 image gets a 2:1 view, so its pixels are square.
+    let (upper_left, lower_right) = LOCATIONS[0].corners((200, 100), 1.0, 1.0);
+    let view = lower_right - upper_left;
+    assert!((view.re / -view.im - 2.0).abs() < 1e-9);
+}
+
+/// Return the (column, row) of the pixel containing `point`, the inverse of
+/// `pixel_to_point`. The arguments `bounds`, `upper_left`, and `lower_right`
+/// are as for that function. If `point` lies outside the image, return
+/// `None`.
+pub fn point_to_pixel(bounds: (usize, usize),
+                      point: Complex<f64>,
+                      upper_left: Complex<f64>,
+                      lower_right: Complex<f64>)
+    -> Option<(usize, usize)>
+{
+    let (width, height) = (lower_right.re - upper_left.re,
+                           upper_left.im - lower_right.im);
+    let column = ((point.re - upper_left.re) / width * bounds.0 as f64).floor();
+    let row = ((upper_left.im - point.im) / height * bounds.1 as f64).floor();
+    if column < 0.0 || column >= bounds.0 as f64 || row < 0.0 || row >= bounds.1 as f64 {
+        return None;
+    }
+    Some((column as usize, row as usize))
+}
+
+#[test]
+fn test_point_to_pixel() {
+    // Use an image that is wider than it is tall, and a view that isn't
+    // centered on the origin, so that swapping rows and columns, or flipping
+    // either axis, can't go unnoticed.
+    let bounds = (160, 90);
+    let upper_left = Complex { re: -1.75, im: 0.5 };
+    let lower_right = Complex { re: 0.25, im: -0.625 };
+    let pixel_size = 2.0 / 160.0;
+
+    // The corner pixels land a pixel's width inside the view's corners at
+    // most.
+    let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < 1e-9;
+    let corner = |pixel| pixel_to_point(bounds, pixel, upper_left, lower_right);
+    assert!(close(corner((0, 0)), upper_left));
+    assert!(close(corner((159, 0)),
+                  Complex { re: lower_right.re - pixel_size, im: upper_left.im }));
+    assert!(close(corner((0, 89)),
+                  Complex { re: upper_left.re, im: lower_right.im + pixel_size }));
+    assert!(close(corner((159, 89)),
+                  Complex { re: lower_right.re - pixel_size,
+                            im: lower_right.im + pixel_size }));
+
+    // The center of every pixel maps back to that pixel.
+    let half = Complex { re: pixel_size / 2.0, im: -pixel_size / 2.0 };
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let center = corner((column, row)) + half;
+            assert_eq!(point_to_pixel(bounds, center, upper_left, lower_right),
+                       Some((column, row)));
+        }
+    }
+
+    // Points outside the view have no pixel.
+    for &(re, im) in &[(-2.0, 0.0), (0.5, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+        assert_eq!(point_to_pixel(bounds, Complex { re, im }, upper_left, lower_right),
+                   None);
+    }
+}
+
+/// A shape whose distance from each orbit `ColorMode::OrbitTrap` shades
+/// points by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trap {
+    /// The origin.
+    Point,
+
+    /// The real and imaginary axes.
+    Cross,
+}
+
+impl Trap {
+    /// Return the distance from `z` to this trap.
+    fn distance(self, z: Complex<f64>) -> f64 {
+        match self {
+            Trap::Point => z.norm(),
+            Trap::Cross => z.re.abs().min(z.im.abs()),
+        }
+    }
+}
+
+impl FromStr for Trap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "point" => Ok(Trap::Point),
+            "cross" => Ok(Trap::Cross),
+            _ => Err(format!("unknown trap: {}", s))
+        }
+    }
+}
+
+#[test]
+fn test_trap_distance() {
+    // The orbit of -1 goes 0, -1, 0, -1, ..., so it starts on both traps.
+    let c = Complex { re: -1.0, im: 0.0 };
+    for &trap in &[Trap::Point, Trap::Cross] {
+        let formula = Formula { trap: Some(trap), ..Formula::default() };
+        assert_eq!(iterate(c, &formula).trap_distance, 0.0);
+    }
+
+    // The orbit of 1 goes 0, 1, 2, 5, escaping after its third step; it
+    // comes closest to the origin at 1, but lies on the real axis throughout.
+    let c = Complex { re: 1.0, im: 0.0 };
+    let point = Formula { trap: Some(Trap::Point), ..Formula::default() };
+    let cross = Formula { trap: Some(Trap::Cross), ..Formula::default() };
+    assert_eq!(iterate(c, &point).trap_distance, 1.0);
+    assert_eq!(iterate(c, &cross).trap_distance, 0.0);
+
+    // The orbit of 1 + i goes 0, 1 + i, 1 + 3i, and escapes.
+    let c = Complex { re: 1.0, im: 1.0 };
+    assert_eq!(iterate(c, &point).trap_distance, 2.0f64.sqrt());
+    assert_eq!(iterate(c, &cross).trap_distance, 1.0);
+
+    // Tracking a trap doesn't change anything else.
+    let c = Complex { re: -0.75, im: 0.1 };
+    assert_eq!(iterate(c, &point).count, escape_time(c, &Formula::default()));
+    assert_eq!(iterate(c, &Formula::default()).trap_distance, 0.0);
+}
+
+/// The fractals we know how to draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fractal {
+    /// The Mandelbrot set, or a multibrot set if the power isn't two.
+    Mandelbrot,
+
+    /// The "Burning Ship" fractal, which replaces each `z` with the absolute
+    /// values of its real and imaginary parts before raising it to the
+    /// power. Since our images put positive imaginary numbers at the top,
+    /// the ship appears upside down; a good view of the whole thing is from
+    /// `-2.2,1.0` to `1.4,-1.7`, and there is a miniature ship on the antenna
+    /// off its bow, around `-1.76,-0.015`.
+    BurningShip,
+}
+
+impl FromStr for Fractal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "mandelbrot" => Ok(Fractal::Mandelbrot),
+            "burning-ship" => Ok(Fractal::BurningShip),
+            _ => Err(format!("unknown fractal: {}", s))
+        }
+    }
+}
+
+/// How to shade points that seem to be members of the Mandelbrot set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InteriorColoring {
+    /// Paint them all the flat gray `Shading::set_color`, which is black
+    /// unless asked otherwise.
+    Black,
+
+    /// Shade them by the angle of the last `z` in their orbit, which brings
+    /// out the structure of the set's bulbs.
+    LastZ,
+
+    /// Shade them by the magnitude of the last `z` in their orbit, from black
+    /// at zero to white at two, the largest it can be without escaping.
+    Magnitude,
+}
+
+impl FromStr for InteriorColoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "black" => Ok(InteriorColoring::Black),
+            "last-z" => Ok(InteriorColoring::LastZ),
+            "magnitude" => Ok(InteriorColoring::Magnitude),
+            _ => Err(format!("unknown interior coloring: {}", s))
+        }
+    }
+}
+
+/// Settings controlling how `render` turns escape times into gray levels.
+#[derive(Clone, Copy, Debug)]
+pub struct Shading {
+    /// How to shade points in the set.
+    pub interior: InteriorColoring,
+
+    /// With `InteriorColoring::Black`, the gray level to paint points in
+    /// the set.
+    pub set_color: u8,
+
+    /// If set, shade escaping points by their escape count modulo this value,
+    /// so that every run of `modulo` counts sweeps the full range of grays.
+    /// Otherwise, the range from zero to the iteration limit sweeps it once.
+    pub modulo: Option<usize>,
+
+    /// How the grays repeat when `modulo` is set, or `repeats` is more than
+    /// one.
+    pub cycle: CycleMode,
+
+    /// When `modulo` is not set, how many times the range from zero to the
+    /// iteration limit sweeps the full range of grays. With
+    /// `CycleMode::Sawtooth`, escape count `count` is shaded as
+    /// `count * repeats % limit` would be with one sweep; with
+    /// `CycleMode::Triangle`, each sweep runs to dark and back to light.
+    pub repeats: usize,
+
+    /// How escape counts are scaled onto the range of grays.
+    pub normalize: Normalization,
+
+    /// What to base the shades of escaping points on.
+    pub color_mode: ColorMode,
+
+    /// With `ColorMode::Lighting`, the direction the light comes from, in
+    /// degrees counterclockwise from the positive real axis.
+    pub light_angle: f64,
+
+    /// With `ColorMode::Lighting`, the light's height above the plane,
+    /// relative to its distance along it. Higher lights flatten the relief.
+    pub light_height: f64,
+}
+
+impl Default for Shading {
+    fn default() -> Shading {
+        Shading {
+            interior: InteriorColoring::Black,
+            set_color: 0,
+            modulo: None,
+            cycle: CycleMode::Sawtooth,
+            repeats: 1,
+            normalize: Normalization::Linear,
+            color_mode: ColorMode::EscapeTime,
+            light_angle: 45.0,
+            light_height: 1.5,
+        }
+    }
+}
+
+/// How shades repeat when escape counts are taken modulo some value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CycleMode {
+    /// Sweep from light to dark, then jump back to light, leaving a sharp
+    /// edge between each band.
+    Sawtooth,
+
+    /// Sweep from light to dark and back again, so the bands run into each
+    /// other without edges.
+    Triangle,
+}
+
+impl FromStr for CycleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "saw" => Ok(CycleMode::Sawtooth),
+            "triangle" => Ok(CycleMode::Triangle),
+            _ => Err(format!("unknown cycle mode: {}", s))
+        }
+    }
+}
+
+/// How to scale escape counts onto the range of grays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    /// Give every count the same share of the grays.
+    Linear,
+
+    /// Scale by the logarithm of the count, spreading out the low counts and
+    /// packing the high ones together. Deep zooms, where most points take a
+    /// long time to escape but differ by only a little, look best this way.
+    Log,
+
+    /// Scale by the square root of the count: a milder form of `Log`.
+    Sqrt,
+}
+
+impl Normalization {
+    /// Return `count`, scaled as this normalization says. Only the ratio of
+    /// one scaled count to another means anything.
+    fn apply(self, count: f64) -> f64 {
+        match self {
+            Normalization::Linear => count,
+            Normalization::Log => count.max(0.0).ln_1p(),
+            Normalization::Sqrt => count.max(0.0).sqrt(),
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "linear" => Ok(Normalization::Linear),
+            "log" => Ok(Normalization::Log),
+            "sqrt" => Ok(Normalization::Sqrt),
+            _ => Err(format!("unknown normalization: {}", s))
+        }
+    }
+}
+
+/// What to base the shades of escaping points on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    /// Their escape counts.
+    EscapeTime,
+
+    /// Their escape counts, smoothed by how far past the bailout radius each
+    /// orbit landed, as `smooth_count` computes, so that the grays change
+    /// gradually rather than in bands.
+    Smooth,
+
+    /// Their estimated distance from the set, on a logarithmic scale. This
+    /// draws the set's thin filaments crisply, even where they are too fine
+    /// for any pixel's center to land in the set.
+    Distance,
+
+    /// Their escape counts' ranks among all the escaping points in the image.
+    /// This spreads the grays evenly over the pixels, instead of spending
+    /// most of them on the few that take long to escape.
+    Histogram,
+
+    /// How close their orbits came to an orbit trap, darkest where the orbit
+    /// passed right through it. The `Formula`'s `trap` field says which trap.
+    OrbitTrap,
+
+    /// Their surface normals, lit by a distant light as if the set lay at the
+    /// bottom of a valley. The `Shading`'s `light_angle` and `light_height`
+    /// fields say where the light is.
+    Lighting,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "escape-time" => Ok(ColorMode::EscapeTime),
+            "smooth" => Ok(ColorMode::Smooth),
+            "distance" => Ok(ColorMode::Distance),
+            "histogram" => Ok(ColorMode::Histogram),
+            "orbit-trap" => Ok(ColorMode::OrbitTrap),
+            "lighting" => Ok(ColorMode::Lighting),
+            _ => Err(format!("unknown color mode: {}", s))
+        }
+    }
+}
+
+impl Shading {
+    /// Return the gray level for a point that escaped after `count`
+    /// iterations, out of a limit of `limit`.
+    fn escaped_shade(&self, count: usize, limit: usize) -> u8 {
+        let (count, range) = match (self.modulo, self.cycle) {
+            (None, CycleMode::Sawtooth) if self.repeats > 1 =>
+                (count * self.repeats % limit, limit),
+            (None, CycleMode::Triangle) if self.repeats > 1 => {
+                // As for `modulo` below, with each sweep taking `limit`
+                // steps of `2 * repeats`.
+                let phase = count * 2 * self.repeats % (2 * limit);
+                (phase.min(2 * limit - phase), limit + 1)
+            }
+            (None, _) => (count, limit),
+            (Some(modulo), CycleMode::Sawtooth) => (count % modulo, modulo),
+            (Some(modulo), CycleMode::Triangle) => {
+                // Count up to `modulo` and back down again. The peak is one
+                // step further than the sawtooth reaches, so scale by one
+                // more to keep it from turning black like the interior.
+                let phase = count % (2 * modulo);
+                (phase.min(2 * modulo - phase), modulo + 1)
+            }
+        };
+        match self.normalize {
+            Normalization::Linear => 255 - (count * 255 / range) as u8,
+            normalize => {
+                let scaled = normalize.apply(count as f64) / normalize.apply(range as f64);
+                255 - (scaled * 255.0) as u8
+            }
+        }
+    }
+
+    /// Return the gray level for a point whose smoothed escape count is
+    /// `count`, out of a limit of `limit`, shading it just as `escaped_shade`
+    /// would a whole count.
+    fn smooth_shade(&self, count: f64, limit: usize) -> u8 {
+        255 - self.smooth_darkness(count, limit) as u8
+    }
+
+    /// Return how far below white `smooth_shade` puts a point whose smoothed
+    /// escape count is `count`, out of a limit of `limit`, before rounding it
+    /// to a whole gray level.
+    fn smooth_darkness(&self, count: f64, limit: usize) -> f64 {
+        let (count, range) = match (self.modulo, self.cycle) {
+            (None, CycleMode::Sawtooth) if self.repeats > 1 =>
+                (count * self.repeats as f64 % limit as f64, limit as f64),
+            (None, CycleMode::Triangle) if self.repeats > 1 => {
+                let period = 2.0 * limit as f64;
+                let phase = count * 2.0 * self.repeats as f64 % period;
+                (phase.min(period - phase), limit as f64 + 1.0)
+            }
+            (None, _) => (count, limit as f64),
+            (Some(modulo), CycleMode::Sawtooth) => (count % modulo as f64, modulo as f64),
+            (Some(modulo), CycleMode::Triangle) => {
+                let period = 2.0 * modulo as f64;
+                let phase = count % period;
+                (phase.min(period - phase), modulo as f64 + 1.0)
+            }
+        };
+        (self.normalize.apply(count) * 255.0 / self.normalize.apply(range)).min(255.0)
+    }
+
+    /// Return the gray level for a point that escaped, whose distance from the
+    /// set is estimated to be `distance` pixels. Points a quarter of a pixel
+    /// away or closer are black; each doubling of the distance after that
+    /// lightens the gray by an eighth, reaching white at 64 pixels.
+    fn distance_shade(&self, distance: f64) -> u8 {
+        ((distance.log2() + 2.0) / 8.0 * 255.0).round().clamp(0.0, 255.0) as u8
+    }
+
+    /// Return the gray level for a point that escaped, whose orbit came within
+    /// `distance` of the trap. Orbits passing through the trap are black, and
+    /// the gray lightens with the square root of the distance, to bring out
+    /// detail close to the trap, reaching white at a distance of two.
+    fn trap_shade(&self, distance: f64) -> u8 {
+        ((distance / 2.0).sqrt() * 255.0).round().min(255.0) as u8
+    }
+
+    /// Return how brightly the light falls on a surface whose normal points
+    /// along `normal` on the plane, from zero to one. The normal's tilt is
+    /// fixed; only its direction varies, so this is the Lambertian dot
+    /// product `normal · light + height`, scaled to reach one when the
+    /// normal points straight at the light.
+    fn lighting(&self, normal: Complex<f64>) -> f64 {
+        let light = Complex::from_polar(1.0, self.light_angle.to_radians());
+        let dot = normal.re * light.re + normal.im * light.im;
+        ((dot + self.light_height) / (1.0 + self.light_height)).max(0.0)
+    }
+
+    /// Return the gray level for a point in the set whose orbit ended at `z`.
+    fn interior_shade(&self, z: Complex<f64>) -> u8 {
+        use std::f64::consts::PI;
+
+        match self.interior {
+            InteriorColoring::Black => self.set_color,
+            InteriorColoring::LastZ => ((z.arg() + PI) / (2.0 * PI) * 255.0) as u8,
+            InteriorColoring::Magnitude => (z.norm() / 2.0 * 255.0).min(255.0) as u8
+        }
+    }
+}
+
+#[test]
+fn test_escaped_shade_repeats() {
+    let limit = 100;
+    let shading = Shading { repeats: 2, ..Shading::default() };
+    let ramp: Vec<u8> = (0..limit).map(|count| shading.escaped_shade(count, limit)).collect();
+
+    // Each half of the ramp sweeps from white nearly to black, just as the
+    // whole ramp does with a single sweep.
+    let (first, second) = ramp.split_at(limit / 2);
+    assert_eq!(first, second);
+    assert_eq!(first[0], 255);
+    assert_eq!(first[49], Shading::default().escaped_shade(98, limit));
+    assert!(first.windows(2).all(|pair| pair[0] >= pair[1]));
+    assert!(ramp[50] > ramp[49]);
+
+    // Smooth shading repeats the same way, without a seam at the wrap.
+    assert_eq!(shading.smooth_shade(25.0, limit), shading.smooth_shade(75.0, limit));
+    assert_eq!(shading.smooth_shade(30.0, limit), shading.escaped_shade(30, limit));
+
+    // With the triangle cycle, each sweep runs back to where it started, so
+    // the sweeps loop without a seam.
+    let shading = Shading { repeats: 2, cycle: CycleMode::Triangle, ..Shading::default() };
+    let formula = Formula { limit, ..Formula::default() };
+    let shades = count_shades(&formula, &shading);
+    assert_eq!(shading.escaped_shade(limit / 2, limit), shades[0]);
+    assert_eq!(shades[0], 255);
+    assert_eq!(shades[..50], shades[50..]);
+    for k in 1..25 {
+        assert_eq!(shades[k], shades[50 - k]);
+        assert!(shades[k] > shades[k + 1]);
+    }
+    assert!(shades[25] < 8);
+    assert_eq!(shading.smooth_shade(10.0, limit), shades[10]);
+    assert_eq!(shading.smooth_shade(40.0, limit), shades[10]);
+}
+
+#[test]
+fn test_escaped_shade_triangle() {
+    let shading = Shading {
+        modulo: Some(4),
+        cycle: CycleMode::Triangle,
+        ..Shading::default()
+    };
+    let shades: Vec<u8> = (0..17).map(|count| shading.escaped_shade(count, 255))
+        .collect();
+
+    // Each cycle is symmetric about its darkest point, and repeats exactly.
+    assert_eq!(&shades[0..9], &[255, 204, 153, 102, 51, 102, 153, 204, 255]);
+    assert_eq!(&shades[0..9], &shades[8..17]);
+
+    // There are no jumps at the cycle boundaries.
+    assert!(shades.windows(2).all(|w| (w[0] as i32 - w[1] as i32).abs() == 51));
+}
+
+#[test]
+fn test_lighting() {
+    let shading = Shading { light_angle: 90.0, light_height: 0.5, ..Shading::default() };
+
+    // A surface facing the light is fully lit, one facing away from it gets
+    // nothing, and one edge-on to it gets what the height alone provides.
+    let lit = |re, im| shading.lighting(Complex { re, im });
+    assert!((lit(0.0, 1.0) - 1.0).abs() < 1e-9);
+    assert_eq!(lit(0.0, -1.0), 0.0);
+    assert!((lit(1.0, 0.0) - 1.0 / 3.0).abs() < 1e-9);
+
+    // A real escaping pixel lands somewhere in between.
+    let formula = Formula { estimate_normal: true, ..Formula::default() };
+    let escape = iterate(Complex { re: -0.75, im: 0.2 }, &formula);
+    assert!(escape.count.is_some());
+    let brightness = shading.lighting(escape.normal);
+    assert!((0.0..=1.0).contains(&brightness));
+}
+
+#[test]
+fn test_escaped_shade_modulo() {
+    let shading = Shading { modulo: Some(10), ..Shading::default() };
+    for count in 0..30 {
+        assert_eq!(shading.escaped_shade(count, 255),
+                   shading.escaped_shade(count + 10, 255));
+    }
+    assert_eq!(shading.escaped_shade(0, 255), 255);
+    assert_eq!(shading.escaped_shade(5, 255), 255 - 127);
+
+    // Without a modulo, the whole range up to the limit is used.
+    let shading = Shading::default();
+    assert_eq!(shading.escaped_shade(10, 255), 245);
+    assert_ne!(shading.escaped_shade(0, 255), shading.escaped_shade(10, 255));
+}
+
+#[test]
+fn test_normalize() {
+    let shading = |normalize| Shading { normalize, ..Shading::default() };
+    let (linear, log, sqrt) = (shading(Normalization::Linear), shading(Normalization::Log),
+                               shading(Normalization::Sqrt));
+
+    // Every normalization runs from white to nearly black.
+    for shading in &[linear, log, sqrt] {
+        assert_eq!(shading.escaped_shade(0, 1000), 255);
+        assert!(shading.escaped_shade(999, 1000) <= 1);
+        assert_eq!(shading.smooth_shade(0.0, 1000), 255);
+        assert_eq!(shading.smooth_shade(-0.2, 1000), 255);
+    }
+
+    // The logarithm packs high counts together, and spreads low ones out,
+    // more than the square root does.
+    let step = |shading: &Shading, count| {
+        shading.escaped_shade(count, 1000) - shading.escaped_shade(count + 50, 1000)
+    };
+    assert!(step(&log, 900) < step(&sqrt, 900) && step(&sqrt, 900) < step(&linear, 900));
+    assert!(step(&log, 10) > step(&sqrt, 10) && step(&sqrt, 10) > step(&linear, 10));
+
+    // Smoothed counts are normalized the same way.
+    let smooth_step = |shading: &Shading, count| {
+        shading.smooth_shade(count, 1000) - shading.smooth_shade(count + 50.5, 1000)
+    };
+    assert!(smooth_step(&log, 900.25) < smooth_step(&linear, 900.25));
+
+    assert_eq!(Normalization::from_str("sqrt"), Ok(Normalization::Sqrt));
+    assert!(Normalization::from_str("cube").is_err());
+}
+
+#[test]
+fn test_distance_shade() {
+    let shading = Shading { color_mode: ColorMode::Distance, ..Shading::default() };
+    assert_eq!(shading.distance_shade(0.0), 0);
+    assert_eq!(shading.distance_shade(0.25), 0);
+    assert_eq!(shading.distance_shade(1.0), 64);
+    assert_eq!(shading.distance_shade(64.0), 255);
+    assert_eq!(shading.distance_shade(1e9), 255);
+}
+
+/// Return the gray level `shading` gives each escape count from zero up to
+/// `formula.limit - 1`, in order, as `ColorMode::EscapeTime` shades them:
+/// the whole range of grays an image drawn with these settings can use for
+/// the points outside the set.
+pub fn count_shades(formula: &Formula, shading: &Shading) -> Vec<u8> {
+    (0..formula.limit)
+        .map(|count| shading.escaped_shade(count, formula.limit))
+        .collect()
+}
+
+#[test]
+fn test_count_shades() {
+    let formula = Formula { limit: 100, ..Formula::default() };
+    let shades = count_shades(&formula, &Shading::default());
+    assert_eq!(shades.len(), 100);
+    assert_eq!((shades[0], shades[50], shades[99]), (255, 128, 3));
+    assert!(shades.windows(2).all(|pair| pair[0] >= pair[1]));
+
+    let triangle = Shading { modulo: Some(4), cycle: CycleMode::Triangle,
+                             ..Shading::default() };
+    assert_eq!(count_shades(&formula, &triangle)[..9],
+               [255, 204, 153, 102, 51, 102, 153, 204, 255]);
+}
+
+/// Render a rectangle of the Mandelbrot set into a buffer of escape results.
+///
+/// The `bounds` argument gives the width and height of the buffer `escapes`,
+/// which holds one `Escape` per pixel. The `upper_left` and `lower_right`
+/// arguments specify points on the complex plane corresponding to the upper-
+/// left and lower-right corners of the buffer. `formula` says how to iterate
+/// each point.
+pub fn render(escapes: &mut [Escape],
+              bounds: (usize, usize),
+              upper_left: Complex<f64>,
+              lower_right: Complex<f64>,
+              formula: &Formula)
+{
+    assert!(escapes.len() == bounds.0 * bounds.1);
+
+    if bounds.0 == 0 {
+        return;
+    }
+    for (row, band) in escapes.chunks_mut(bounds.0).enumerate() {
+        iterate_row(band, |column| pixel_to_point(bounds, (column, row),
+                                                  upper_left, lower_right),
+                    formula);
+    }
+}
+
+/// Set each element `escapes[i]` of a row of pixels to the result of
+/// iterating `point(i)` under `formula`, a few lanes at a time.
+fn iterate_row<P>(escapes: &mut [Escape], point: P, formula: &Formula)
+    where P: Fn(usize) -> Complex<f64>
+{
+    let whole = escapes.len() - escapes.len() % LANES;
+    let (lanes, rest) = escapes.split_at_mut(whole);
+    for (i, chunk) in lanes.chunks_exact_mut(LANES).enumerate() {
+        let mut c = [Complex::default(); LANES];
+        for (k, c) in c.iter_mut().enumerate() {
+            *c = point(i * LANES + k);
+        }
+        chunk.copy_from_slice(&iterate_lanes(c, formula));
+    }
+    for (i, escape) in rest.iter_mut().enumerate() {
+        *escape = iterate(point(whole + i), formula);
+    }
+}
+
+/// Render an image of the Mandelbrot set with the given `bounds` and
+/// corners, as `render` does, but spread the work across all available
+/// threads. Return the escape results for each pixel, in row-major order.
+pub fn render_parallel(bounds: (usize, usize),
+                       upper_left: Complex<f64>,
+                       lower_right: Complex<f64>,
+                       formula: &Formula)
+    -> Vec<Escape>
+{
+    render_parallel_counting(bounds, upper_left, lower_right, formula,
+                             &AtomicUsize::new(0))
+}
+
+/// Like `render_parallel`, but add the number of pixels in each row to
+/// `pixels_done` as soon as the row is finished, so that another thread can
+/// watch the render's progress.
+pub fn render_parallel_counting(bounds: (usize, usize),
+                                upper_left: Complex<f64>,
+                                lower_right: Complex<f64>,
+                                formula: &Formula,
+                                pixels_done: &AtomicUsize)
+    -> Vec<Escape>
+{
+    render_parallel_stoppable(bounds, upper_left, lower_right, formula, pixels_done,
+                              &AtomicBool::new(false)).0
+}
+
+/// Like `render_parallel_counting`, but give up on the rows not yet started
+/// once `stop` is set, so that another thread can cut the render short. The
+/// rows given up on are left as `Escape::default()`, which shades them as
+/// points in the set; rows already under way are finished. Return the
+/// escape results, and whether any rows were given up on: if `stop` is only
+/// set once every row is under way, the render is complete all the same.
+pub fn render_parallel_stoppable(bounds: (usize, usize),
+                                 upper_left: Complex<f64>,
+                                 lower_right: Complex<f64>,
+                                 formula: &Formula,
+                                 pixels_done: &AtomicUsize,
+                                 stop: &AtomicBool)
+    -> (Vec<Escape>, bool)
+{
+    let mut escapes = vec![Escape::default(); bounds.0 * bounds.1];
+    let stopped = AtomicBool::new(false);
+
+    // Scope of slicing up `escapes` into horizontal bands.
+    {
+        let bands: Vec<(usize, &mut [Escape])> = escapes
+            .chunks_mut(bounds.0)
+            .enumerate()
+            .collect();
+
+        bands.into_par_iter()
+            .for_each(|(i, band)| {
+                if stop.load(Ordering::Relaxed) {
+                    stopped.store(true, Ordering::Relaxed);
+                    return;
+                }
+                let top = i;
+                let band_bounds = (bounds.0, 1);
+                let band_upper_left = pixel_to_point(bounds, (0, top),
+                                                     upper_left, lower_right);
+                let band_lower_right = pixel_to_point(bounds, (bounds.0, top + 1),
+                                                      upper_left, lower_right);
+                render(band, band_bounds, band_upper_left, band_lower_right,
+                       formula);
+                pixels_done.fetch_add(band.len(), Ordering::Relaxed);
+            });
+    }
+
+    (escapes, stopped.into_inner())
+}
+
+#[test]
+fn test_render_parallel_counting() {
+    let pixels_done = AtomicUsize::new(0);
+    let escapes = render_parallel_counting((10, 7),
+                                           Complex { re: -2.0, im: 1.0 },
+                                           Complex { re: 1.0, im: -1.0 },
+                                           &Formula::default(), &pixels_done);
+    assert_eq!(pixels_done.load(Ordering::Relaxed), 70);
+    assert_eq!(escapes.len(), 70);
+}
+
+#[test]
+fn test_render_parallel_stoppable() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let formula = Formula::default();
+
+    // Left alone, the render is complete, and says it wasn't stopped.
+    let pixels_done = AtomicUsize::new(0);
+    let (escapes, stopped) =
+        render_parallel_stoppable(bounds, upper_left, lower_right, &formula,
+                                  &pixels_done, &AtomicBool::new(false));
+    assert_eq!(escapes, render_parallel(bounds, upper_left, lower_right, &formula));
+    assert_eq!(pixels_done.load(Ordering::Relaxed), 40 * 30);
+    assert!(!stopped);
+
+    // Stopped before it starts, as a Ctrl-C might, every row is given up on
+    // and left as points in the set.
+    let pixels_done = AtomicUsize::new(0);
+    let (escapes, stopped) =
+        render_parallel_stoppable(bounds, upper_left, lower_right, &formula,
+                                  &pixels_done, &AtomicBool::new(true));
+    assert_eq!(escapes.len(), 40 * 30);
+    let rendered = escapes.chunks(bounds.0)
+        .filter(|row| row.iter().any(|escape| *escape != Escape::default()))
+        .count();
+    assert_eq!(rendered, 0);
+    assert_eq!(pixels_done.load(Ordering::Relaxed), 0);
+    assert!(stopped);
+
+    // Stopped partway through, once a few rows are done, the rows already
+    // drawn survive, and the rest are either finished or left alone.
+    let bounds = (40, 300);
+    let full = render_parallel(bounds, upper_left, lower_right, &formula);
+    let pixels_done = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (escapes, stopped) = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while pixels_done.load(Ordering::Relaxed) < 4 * bounds.0 {
+                std::thread::yield_now();
+            }
+            stop.store(true, Ordering::Relaxed);
+        });
+        render_parallel_stoppable(bounds, upper_left, lower_right, &formula,
+                                  &pixels_done, &stop)
+    });
+    assert_eq!(escapes.len(), full.len());
+    let mut rendered = 0;
+    for (row, expected) in escapes.chunks(bounds.0).zip(full.chunks(bounds.0)) {
+        if row == expected {
+            rendered += 1;
+        } else {
+            assert!(row.iter().all(|escape| *escape == Escape::default()));
+        }
+    }
+    assert!(rendered >= 4);
+    assert_eq!(rendered * bounds.0, pixels_done.load(Ordering::Relaxed));
+    assert_eq!(stopped, rendered < bounds.1);
+
+    // The partial render still makes an image.
+    let mut pixels = vec![0; escapes.len()];
+    shade(&mut pixels, &escapes, &formula, 3.0 / 40.0, &Shading::default());
+    let filename = std::env::temp_dir()
+        .join(format!("mandelbrot-stopped-{}.png", std::process::id()));
+    let filename = filename.to_str().unwrap();
+    write_image(filename, &pixels, bounds).unwrap();
+    assert!(std::fs::metadata(filename).unwrap().len() > 0);
+    std::fs::remove_file(filename).unwrap();
+}
+
+/// Return the point on the complex plane that `pixel` shows when the view
+/// from `upper_left` to `lower_right` is turned `degrees` counterclockwise
+/// about its center. The arguments are otherwise as for `pixel_to_point`.
+///
+/// Turning the view counterclockwise turns the image clockwise. Rotation
+/// keeps distances as they are, so square pixels stay square.
+pub fn rotated_pixel_to_point(bounds: (usize, usize),
+                              pixel: (usize, usize),
+                              upper_left: Complex<f64>,
+                              lower_right: Complex<f64>,
+                              degrees: f64)
+    -> Complex<f64>
+{
+    let center = (upper_left + lower_right) / 2.0;
+    let turn = Complex::from_polar(1.0, degrees.to_radians());
+    center + (pixel_to_point(bounds, pixel, upper_left, lower_right) - center) * turn
+}
+
+#[test]
+fn test_rotated_pixel_to_point() {
+    let bounds = (100, 100);
+    let upper_left = Complex { re: -1.5, im: 1.0 };
+    let lower_right = Complex { re: 0.5, im: -1.0 };
+    let point = |pixel, degrees| rotated_pixel_to_point(bounds, pixel, upper_left,
+                                                        lower_right, degrees);
+    let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < 1e-12;
+
+    // A quarter turn takes the top center of the image to the left center.
+    let top_center = (50, 0);
+    let left_center = (0, 50);
+    assert!(close(point(top_center, 90.0),
+                  pixel_to_point(bounds, left_center, upper_left, lower_right)));
+    assert!(close(point(left_center, -90.0),
+                  pixel_to_point(bounds, top_center, upper_left, lower_right)));
+
+    // The center stays put, and no turn at all changes nothing.
+    assert!(close(point((50, 50), 37.0), Complex { re: -0.5, im: 0.0 }));
+    for &pixel in &[(0, 0), (17, 83), (99, 99)] {
+        assert!(close(point(pixel, 0.0),
+                      pixel_to_point(bounds, pixel, upper_left, lower_right)));
+        assert!(close(point(pixel, 360.0), point(pixel, 0.0)));
+    }
+}
+
+/// Like `render_parallel_counting`, but turn the view `degrees`
+/// counterclockwise about its center, as `rotated_pixel_to_point` does.
+pub fn render_rotated(bounds: (usize, usize),
+                      upper_left: Complex<f64>,
+                      lower_right: Complex<f64>,
+                      degrees: f64,
+                      formula: &Formula,
+                      pixels_done: &AtomicUsize)
+    -> Vec<Escape>
+{
+    let mut escapes = vec![Escape::default(); bounds.0 * bounds.1];
+    escapes.par_chunks_mut(bounds.0.max(1))
+        .enumerate()
+        .for_each(|(row, band)| {
+            iterate_row(band, |column| rotated_pixel_to_point(bounds, (column, row),
+                                                              upper_left, lower_right,
+                                                              degrees),
+                        formula);
+            pixels_done.fetch_add(band.len(), Ordering::Relaxed);
+        });
+    escapes
+}
+
+#[test]
+fn test_render_rotated() {
+    let bounds = (31, 20);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.1, im: -1.0 };
+    let formula = Formula::default();
+    let pixels_done = AtomicUsize::new(0);
+    let turned = render_rotated(bounds, upper_left, lower_right, 30.0, &formula,
+                                &pixels_done);
+    assert_eq!(pixels_done.load(Ordering::Relaxed), 31 * 20);
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let c = rotated_pixel_to_point(bounds, (column, row), upper_left,
+                                           lower_right, 30.0);
+            assert_eq!(turned[row * bounds.0 + column].count, escape_time(c, &formula));
+        }
+    }
+    let unturned = render_parallel(bounds, upper_left, lower_right, &formula);
+    assert_ne!(turned, unturned);
+}
+
+/// Render one rectangular tile of an image of the Mandelbrot set, whose size
+/// and corners are given by `bounds`, `upper_left`, and `lower_right`, as
+/// `render_parallel` would render the whole image. `tile` gives the column
+/// and row of the tile's upper-left pixel in the image, and its width and
+/// height. Return the escape results for the tile's pixels only, in
+/// row-major order.
+///
+/// This lets us draw images too large to hold in memory, a tile at a time.
+/// Each point is computed exactly as `render_parallel` computes it, so the
+/// tiles fit together into the same image it would draw.
+pub fn render_tile(bounds: (usize, usize),
+                   tile: (usize, usize, usize, usize),
+                   upper_left: Complex<f64>,
+                   lower_right: Complex<f64>,
+                   formula: &Formula)
+    -> Vec<Escape>
+{
+    let (left, top, width, height) = tile;
+    assert!(left + width <= bounds.0 && top + height <= bounds.1);
+
+    let mut escapes = vec![Escape::default(); width * height];
+    if width == 0 {
+        return escapes;
+    }
+    escapes.par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(i, band)| {
+            // The same corners `render_parallel` uses for this row.
+            let row = top + i;
+            let row_upper_left = pixel_to_point(bounds, (0, row),
+                                                upper_left, lower_right);
+            let row_lower_right = pixel_to_point(bounds, (bounds.0, row + 1),
+                                                 upper_left, lower_right);
+            iterate_row(band, |j| pixel_to_point((bounds.0, 1), (left + j, 0),
+                                                 row_upper_left, row_lower_right),
+                        formula);
+        });
+
+    escapes
+}
+
+#[test]
+fn test_render_tile() {
+    let bounds = (30, 20);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let formula = Formula::default();
+    let whole = render_parallel(bounds, upper_left, lower_right, &formula);
+
+    // Tiles that don't divide the image evenly, stitched back together.
+    let mut stitched = vec![Escape::default(); whole.len()];
+    for top in (0..20).step_by(7) {
+        for left in (0..30).step_by(8) {
+            let (width, height) = (8.min(30 - left), 7.min(20 - top));
+            let tile = render_tile(bounds, (left, top, width, height),
+                                   upper_left, lower_right, &formula);
+            for (i, escape) in tile.into_iter().enumerate() {
+                stitched[(top + i / width) * 30 + left + i % width] = escape;
+            }
+        }
+    }
+    assert_eq!(stitched, whole);
+
+    // A tile needs only its own pixels' worth of memory, however large the
+    // image it belongs to: the whole of this one would take terabytes.
+    let tile = render_tile((1_000_000, 1_000_000), (500_000, 500_000, 4, 3),
+                           upper_left, lower_right, &formula);
+    assert_eq!(tile.len(), 12);
+}
+
+/// Render a rough preview of an image of the Mandelbrot set, whose size and
+/// corners are given by `bounds`, `upper_left`, and `lower_right`, by
+/// iterating only every `step`th pixel of every `step`th row and copying its
+/// result to the `step` by `step` block of pixels below and to its right.
+/// Return results for every pixel of the image, in row-major order, so that
+/// the preview can be shaded and saved like the image itself.
+///
+/// This takes about `step * step` times less time than the image itself,
+/// so a preview can be on screen while the real image is still coming.
+pub fn render_preview(bounds: (usize, usize),
+                      step: usize,
+                      upper_left: Complex<f64>,
+                      lower_right: Complex<f64>,
+                      formula: &Formula)
+    -> Vec<Escape>
+{
+    assert!(step >= 1);
+
+    // Render the sampled pixels as a small image of their own, whose lower
+    // right corner is where the first pixel beyond the last sample would be.
+    let coarse_bounds = (bounds.0.div_ceil(step), bounds.1.div_ceil(step));
+    let coarse_lower_right = pixel_to_point(bounds,
+                                            (coarse_bounds.0 * step,
+                                             coarse_bounds.1 * step),
+                                            upper_left, lower_right);
+    let coarse = render_parallel(coarse_bounds, upper_left, coarse_lower_right, formula);
+
+    let mut escapes = vec![Escape::default(); bounds.0 * bounds.1];
+    for (row, band) in escapes.chunks_mut(bounds.0.max(1)).enumerate() {
+        let coarse_row = &coarse[row / step * coarse_bounds.0..];
+        for (column, escape) in band.iter_mut().enumerate() {
+            *escape = coarse_row[column / step];
+        }
+    }
+    escapes
+}
+
+#[test]
+fn test_render_preview() {
+    let bounds = (37, 21);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.5, im: -1.0 };
+    let formula = Formula::default();
+    let preview = render_preview(bounds, 8, upper_left, lower_right, &formula);
+    assert_eq!(preview.len(), 37 * 21);
+
+    // Each sampled pixel gets the result of iterating its own point, and
+    // the pixels that follow it share it.
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let sample = (column / 8 * 8, row / 8 * 8);
+            let point = pixel_to_point(bounds, sample, upper_left, lower_right);
+            assert_eq!(preview[row * bounds.0 + column].count,
+                       escape_time(point, &formula));
+        }
+    }
+
+    // A step of one is no shortcut at all.
+    assert_eq!(render_preview(bounds, 1, upper_left, lower_right, &formula)
+                   .iter().map(|escape| escape.count).collect::<Vec<_>>(),
+               render_parallel(bounds, upper_left, lower_right, &formula)
+                   .iter().map(|escape| escape.count).collect::<Vec<_>>());
+}
+
+/// If `c` escapes under `formula`, return its orbit: the values of `z` that
+/// iterating it visits, from the first up to and including the one that
+/// escaped. If `c` seems to be in the set, return `None`.
+pub fn orbit(c: Complex<f64>, formula: &Formula) -> Option<Vec<Complex<f64>>> {
+    let count = escape_time(c, formula)?;
+
+    // Now that we know the orbit's length, go around it again, computing
+    // each `z` exactly as `iterate` did.
+    let raise = |z: Complex<f64>| match (formula.fractal, formula.power) {
+        (Fractal::Mandelbrot, 2) => z * z,
+        (Fractal::Mandelbrot, power) => z.powu(power),
+        (Fractal::BurningShip, power) => Complex { re: z.re.abs(), im: z.im.abs() }.powu(power),
+    };
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    Some((0..count).map(|_| { z = raise(z) + c; z }).collect())
+}
+
+#[test]
+fn test_orbit() {
+    let formula = Formula::default();
+    let orbit_of = |re| orbit(Complex { re, im: 0.0 }, &formula);
+    let real = |values: &[f64]| values.iter()
+        .map(|&re| Complex { re, im: 0.0 })
+        .collect::<Vec<_>>();
+    assert_eq!(orbit_of(1.0), Some(real(&[1.0, 2.0, 5.0])));
+    assert_eq!(orbit_of(3.0), Some(real(&[3.0])));
+    assert_eq!(orbit_of(-1.0), None);
+
+    // The orbit is as long as the escape time, and ends where `iterate` did.
+    let c = Complex { re: -0.75, im: 0.1 };
+    let escape = iterate(c, &formula);
+    let orbit = orbit(c, &formula).unwrap();
+    assert_eq!(Some(orbit.len()), escape.count);
+    assert_eq!(orbit.last(), Some(&escape.z));
+}
+
+/// Render a Buddhabrot of the area between `upper_left` and `lower_right`:
+/// rather than coloring each point by how fast it escapes, follow the orbit
+/// of every escaping point, and count how many times the orbits pass through
+/// each pixel of the image. Return the counts for each pixel of an image
+/// with the given `bounds`, in row-major order.
+///
+/// The points whose orbits are followed lie on a grid `samples` times finer
+/// than the image's pixels in each direction. More samples give a smoother
+/// image, at a cost of `samples * samples` times more time.
+pub fn buddhabrot(bounds: (usize, usize),
+                  upper_left: Complex<f64>,
+                  lower_right: Complex<f64>,
+                  formula: &Formula,
+                  samples: usize)
+    -> Vec<u32>
+{
+    assert!(samples >= 1);
+    let sample_bounds = (bounds.0 * samples, bounds.1 * samples);
+
+    // Each thread counts hits in a buffer of its own, and then we add them
+    // up, which is cheaper than sharing one buffer of atomic counters.
+    (0..sample_bounds.1).into_par_iter()
+        .fold(|| vec![0; bounds.0 * bounds.1], |mut hits, row| {
+            for column in 0..sample_bounds.0 {
+                let c = pixel_to_point(sample_bounds, (column, row), upper_left, lower_right);
+                for z in orbit(c, formula).into_iter().flatten() {
+                    if let Some((x, y)) = point_to_pixel(bounds, z, upper_left, lower_right) {
+                        hits[y * bounds.0 + x] += 1;
+                    }
+                }
+            }
+            hits
+        })
+        .reduce(|| vec![0; bounds.0 * bounds.1], |mut total, hits| {
+            for (total, hits) in total.iter_mut().zip(hits) {
+                *total += hits;
+            }
+            total
+        })
+}
+
+/// Convert the Buddhabrot counts `hits` into gray levels, with the pixels
+/// orbits pass through most often white, and those they pass through least
+/// often black. Since every orbit starts at the point it belongs to, a grid
+/// of sample points gives every pixel a few hits at least; this leaves the
+/// background black regardless. The square root brings out the fainter parts
+/// of the image.
+pub fn shade_hits(hits: &[u32]) -> Vec<u8> {
+    let least = hits.iter().copied().min().unwrap_or(0);
+    let most = hits.iter().copied().max().unwrap_or(0);
+    let range = (most - least).max(1) as f64;
+    hits.iter()
+        .map(|&count| (((count - least) as f64 / range).sqrt() * 255.0).round() as u8)
+        .collect()
+}
+
+#[test]
+fn test_buddhabrot() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.5 };
+    let lower_right = Complex { re: 2.0, im: -1.5 };
+    let formula = Formula::default();
+    let hits = buddhabrot(bounds, upper_left, lower_right, &formula, 2);
+    assert_eq!(hits.len(), 40 * 30);
+
+    // Every visit to a pixel by an escaping orbit is counted, once.
+    let sample_bounds = (80, 60);
+    let mut expected = vec![0; 40 * 30];
+    for row in 0..sample_bounds.1 {
+        for column in 0..sample_bounds.0 {
+            let c = pixel_to_point(sample_bounds, (column, row), upper_left, lower_right);
+            for z in orbit(c, &formula).unwrap_or_default() {
+                if let Some((x, y)) = point_to_pixel(bounds, z, upper_left, lower_right) {
+                    expected[y * bounds.0 + x] += 1;
+                }
+            }
+        }
+    }
+    assert_eq!(hits, expected);
+    assert!(hits.iter().any(|&count| count > 0));
+
+    // Points in the set contribute nothing, so a view inside the main
+    // cardioid is black.
+    let inside = buddhabrot((8, 8), Complex { re: -0.2, im: 0.2 },
+                            Complex { re: 0.0, im: 0.0 }, &formula, 1);
+    assert!(inside.iter().all(|&count| count == 0));
+    assert!(shade_hits(&inside).iter().all(|&gray| gray == 0));
+
+    let pixels = shade_hits(&hits);
+    assert_eq!(pixels.iter().max(), Some(&255));
+    assert_eq!(pixels.iter().min(), Some(&0));
+    assert_eq!(shade_hits(&[0, 1, 4]), [0, 128, 255]);
+    assert_eq!(shade_hits(&[16, 17, 20]), [0, 128, 255]);
+}
+
+/// Convert the escape results in `escapes` into gray levels in `pixels`, one
+/// byte per pixel, as directed by `shading`. `formula` is the formula the
+/// results were computed with, and `pixel_size` is the width of a pixel on
+/// the complex plane.
+pub fn shade(pixels: &mut [u8],
+             escapes: &[Escape],
+             formula: &Formula,
+             pixel_size: f64,
+             shading: &Shading)
+{
+    assert!(pixels.len() == escapes.len());
+    let limit = formula.limit;
+
+    let below = match shading.color_mode {
+        ColorMode::Histogram => cumulative_histogram(escapes, limit),
+        _ => vec![]
+    };
+
+    pixels.par_iter_mut()
+        .zip(escapes)
+        .for_each(|(pixel, escape)| {
+            *pixel = match (escape.count, shading.color_mode) {
+                (None, _) => shading.interior_shade(escape.z),
+                (Some(count), ColorMode::EscapeTime) =>
+                    shading.escaped_shade(count, limit),
+                (Some(count), ColorMode::Smooth) =>
+                    shading.smooth_shade(smooth_count(count, escape.z, formula), limit),
+                (Some(_), ColorMode::Distance) =>
+                    shading.distance_shade(escape.distance / pixel_size),
+                (Some(count), ColorMode::Histogram) =>
+                    255 - (below[count] * 255 / below[limit]) as u8,
+                (Some(_), ColorMode::OrbitTrap) =>
+                    shading.trap_shade(escape.trap_distance),
+                (Some(_), ColorMode::Lighting) =>
+                    (shading.lighting(escape.normal) * 255.0).round() as u8
+            };
+        });
+}
+
+/// Return a vector `below` such that `below[k]` is the number of points in
+/// `escapes` that escaped in fewer than `k` iterations, for `k` from zero up
+/// to and including `limit`. So `below[limit]` is the number of points that
+/// escaped at all; points in the set aren't counted.
+fn cumulative_histogram(escapes: &[Escape], limit: usize) -> Vec<usize> {
+    let mut below = vec![0; limit + 1];
+    for count in escapes.iter().filter_map(|escape| escape.count) {
+        below[count + 1] += 1;
+    }
+    for k in 1..=limit {
+        below[k] += below[k - 1];
+    }
+    below
+}
+
+#[test]
+fn test_shade_histogram() {
+    // Most points escape at once, and a few take a long time.
+    let counts = [Some(1), Some(1), Some(1), Some(1), Some(2), Some(2),
+                  Some(100), Some(200), None];
+    let escapes: Vec<Escape> = counts.iter()
+        .map(|&count| Escape { count, ..Escape::default() })
+        .collect();
+    assert_eq!(cumulative_histogram(&escapes, 255)[..4], [0, 0, 4, 6]);
+    assert_eq!(cumulative_histogram(&escapes, 255)[255], 8);
+
+    // Linear shading leaves the quick escapers nearly white, but histogram
+    // shading spreads them out according to how many pixels they cover.
+    let shading = Shading { color_mode: ColorMode::Histogram, ..Shading::default() };
+    let mut pixels = vec![0; escapes.len()];
+    shade(&mut pixels, &escapes, &Formula::default(), 1.0, &shading);
+    assert_eq!(pixels, [255, 255, 255, 255, 128, 128, 64, 32, 0]);
+}
+
+/// The thresholds for ordered dithering: an 8x8 Bayer matrix, arranging the
+/// numbers 0 to 63 so that each is as far as possible from those close to it
+/// in value. Comparing a level's fractional part against the threshold at
+/// each pixel's position mixes the neighboring levels in the right proportion.
+const BAYER: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Reshade the escaping points in `pixels`, shaded by `shade` in
+/// `ColorMode::Smooth`, with ordered dithering: rather than rounding each
+/// smoothed gray level down, round it up or down according to its position,
+/// so that gentle gradients become a fine pattern of the two nearest levels
+/// instead of visible bands. `escapes`, `bounds`, `formula`, and `shading`
+/// are those the pixels were shaded from. Points in the set are left alone.
+pub fn dither_smooth(pixels: &mut [u8],
+                     escapes: &[Escape],
+                     bounds: (usize, usize),
+                     formula: &Formula,
+                     shading: &Shading)
+{
+    assert!(pixels.len() == escapes.len() && escapes.len() == bounds.0 * bounds.1);
+
+    pixels.par_chunks_mut(bounds.0.max(1))
+        .zip(escapes.par_chunks(bounds.0.max(1)))
+        .enumerate()
+        .for_each(|(row, (pixels, escapes))| {
+            for (column, (pixel, escape)) in pixels.iter_mut().zip(escapes).enumerate() {
+                if let Some(count) = escape.count {
+                    let threshold = (BAYER[row % 8][column % 8] as f64 + 0.5) / 64.0;
+                    let darkness = shading.smooth_darkness(
+                        smooth_count(count, escape.z, formula), formula.limit);
+                    *pixel = 255 - (darkness + threshold).min(255.0) as u8;
+                }
+            }
+        });
+}
+
+#[test]
+fn test_dither_smooth() {
+    // Every escaping point here is halfway between two gray levels.
+    let formula = Formula::default();
+    let z = Complex { re: 2.0f64.powf(2.0f64.sqrt()), im: 0.0 };
+    assert!((smooth_count(10, z, &formula) - 10.5).abs() < 1e-12);
+    let bounds = (16, 16);
+    let mut escapes = vec![Escape { count: Some(10), z, ..Escape::default() }; 256];
+    escapes[17] = Escape { count: None, ..Escape::default() };
+
+    let shading = Shading { color_mode: ColorMode::Smooth, ..Shading::default() };
+    let mut pixels = vec![0; 256];
+    shade(&mut pixels, &escapes, &formula, 1.0, &shading);
+    let interior = pixels[17];
+    assert!(pixels.iter().enumerate().all(|(i, &p)| i == 17 || p == 245));
+
+    // Dithering mixes the levels on either side half and half, and leaves
+    // the interior as it was.
+    dither_smooth(&mut pixels, &escapes, bounds, &formula, &shading);
+    assert_eq!(pixels[17], interior);
+    let escaped: Vec<u8> = pixels.iter().enumerate()
+        .filter(|&(i, _)| i != 17)
+        .map(|(_, &p)| p)
+        .collect();
+    assert!(escaped.iter().all(|&p| p == 244 || p == 245));
+    let lighter = escaped.iter().filter(|&&p| p == 245).count();
+    assert!((125..=130).contains(&lighter), "{} lighter pixels", lighter);
+
+    // The thresholds are each number from 0 to 63 once.
+    let mut thresholds: Vec<u8> = BAYER.iter().flatten().copied().collect();
+    thresholds.sort_unstable();
+    assert_eq!(thresholds, (0..64).collect::<Vec<u8>>());
+}
+
+/// Return a heat map of the work done for each pixel of an image whose escape
+/// results are `escapes`: each gray level is proportional to the number of
+/// iterations spent on the pixel, which is its escape count, or the full
+/// `limit` for points in the set.
+///
+/// Points far from the set escape almost at once, while points near its
+/// boundary take many iterations, and points inside it never escape, so
+/// this shows where the time goes.
+pub fn cost_heatmap(escapes: &[Escape], limit: usize) -> Vec<u8> {
+    escapes.par_iter()
+        .map(|escape| {
+            let iterations = escape.count.unwrap_or(limit);
+            (iterations * 255 / limit) as u8
+        })
+        .collect()
+}
+
+/// Render and shade an image with the given `bounds` and corners, returning
+/// its gray levels, one byte per pixel in row-major order.
+pub fn render_pixels(bounds: (usize, usize),
+                     upper_left: Complex<f64>,
+                     lower_right: Complex<f64>,
+                     formula: &Formula,
+                     shading: &Shading)
+    -> Vec<u8>
+{
+    let escapes = render_parallel(bounds, upper_left, lower_right, formula);
+    let mut pixels = vec![0; escapes.len()];
+    let pixel_size = (lower_right.re - upper_left.re) / bounds.0 as f64;
+    shade(&mut pixels, &escapes, formula, pixel_size, shading);
+    pixels
+}
+
+#[test]
+fn test_render_interior_last_z() {
+    // An area well inside the main cardioid.
+    let bounds = (16, 16);
+    let upper_left = Complex { re: -0.3, im: 0.3 };
+    let lower_right = Complex { re: 0.1, im: -0.3 };
+
+    let pixels = render_pixels(bounds, upper_left, lower_right, &Formula::default(),
+                               &Shading::default());
+    assert!(pixels.iter().all(|&p| p == 0));
+
+    // A flat color other than black replaces it exactly.
+    let shading = Shading { set_color: 90, ..Shading::default() };
+    let pixels = render_pixels(bounds, upper_left, lower_right, &Formula::default(),
+                               &shading);
+    assert!(pixels.iter().all(|&p| p == 90));
+
+    // Interior shading needs every orbit followed to the limit, as `main`
+    // arranges.
+    let formula = Formula { skip_interior: false, ..Formula::default() };
+    let shading = Shading { interior: InteriorColoring::LastZ, ..Shading::default() };
+    let mut shades = render_pixels(bounds, upper_left, lower_right, &formula, &shading);
+    shades.sort();
+    shades.dedup();
+    assert!(shades.len() > 10);
+
+    let shading = Shading { interior: InteriorColoring::Magnitude, ..Shading::default() };
+    assert_eq!(shading.interior_shade(Complex { re: 0.0, im: 0.0 }), 0);
+    assert_eq!(shading.interior_shade(Complex { re: 0.0, im: -1.0 }), 127);
+    assert_eq!(shading.interior_shade(Complex { re: 2.0, im: 0.0 }), 255);
+    let mut shades = render_pixels(bounds, upper_left, lower_right, &formula, &shading);
+    shades.sort();
+    shades.dedup();
+    assert!(shades.len() > 10);
+}
+
+#[test]
+fn test_cost_heatmap() {
+    let bounds = (64, 48);
+    let escapes = render_parallel(bounds,
+                                  Complex { re: -2.5, im: 1.2 },
+                                  Complex { re: 1.0, im: -1.2 },
+                                  &Formula::default());
+    let heatmap = cost_heatmap(&escapes, 255);
+
+    // The corner is far outside the set, and escapes immediately.
+    assert!(heatmap[0] < 10);
+
+    // Points near the boundary take longer than points far from it, and
+    // points in the set take longest of all.
+    let index = |column, row| row * bounds.0 + column;
+    let boundary = (0..bounds.0)
+        .map(|column| index(column, bounds.1 / 2))
+        .filter(|&i| escapes[i].count.is_some())
+        .max_by_key(|&i| escapes[i].count)
+        .unwrap();
+    assert!(heatmap[boundary] > heatmap[0]);
+    let interior = escapes.iter().position(|e| e.count.is_none()).unwrap();
+    assert_eq!(heatmap[interior], 255);
+}
+
+#[test]
+fn test_render_distance() {
+    // Hairs and filaments around the period-2 bulb. At this resolution, few
+    // pixel centers land in the set, but distance estimation should still
+    // draw dark lines along them.
+    let bounds = (80, 60);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+    let formula = Formula { estimate_distance: true, ..Formula::default() };
+    let shading = Shading { color_mode: ColorMode::Distance, ..Shading::default() };
+    let by_count = render_pixels(bounds, upper_left, lower_right, &formula,
+                                 &Shading::default());
+    let by_distance = render_pixels(bounds, upper_left, lower_right, &formula,
+                                    &shading);
+
+    // The interior is still black, and escaping pixels beside it, which are
+    // within a pixel or two of the set, are dark.
+    for row in 0..bounds.1 {
+        for column in 1..bounds.0 {
+            let (left, right) = (row * bounds.0 + column - 1, row * bounds.0 + column);
+            if by_count[left] == 0 {
+                assert_eq!(by_distance[left], 0);
+                assert!(by_distance[right] < 128);
+            }
+        }
+    }
+
+    // And there's much more of the set visible than just its interior.
+    let dark = |pixels: &[u8]| pixels.iter().filter(|&&p| p < 64).count();
+    assert!(dark(&by_distance) > 2 * dark(&by_count));
+}
+
+#[test]
+fn test_lemniscates() {
+    // The region inside the lemniscate |z_n| = 2 is exactly the set of points
+    // that haven't escaped after n + 1 iterations. Each such region contains
+    // the next, closing in on the set as n grows.
+    let bounds = (64, 48);
+    let upper_left = Complex { re: -2.5, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let inside = |n: usize| -> Vec<bool> {
+        render_parallel(bounds, upper_left, lower_right,
+                        &Formula { limit: n + 1, ..Formula::default() })
+            .iter()
+            .map(|escape| escape.count.is_none())
+            .collect()
+    };
+
+    let mut outer = inside(1);
+    for &n in &[2, 4, 8, 16] {
+        let inner = inside(n);
+        assert!(inner.iter().zip(&outer).all(|(&i, &o)| !i || o));
+        assert!(inner.iter().filter(|&&i| i).count() <
+                outer.iter().filter(|&&o| o).count());
+        outer = inner;
+    }
+}
+
+#[test]
+fn test_render_degenerate_bounds() {
+    let upper_left = Complex { re: -1.0, im: 0.25 };
+    let lower_right = Complex { re: 0.5, im: -0.25 };
+    let expected = |point| match escape_time(point, &Formula::default()) {
+        None => 0,
+        Some(count) => 255 - count as u8
+    };
+
+    for &bounds in &[(1, 1), (1, 10), (10, 1)] {
+        let pixels = render_pixels(bounds, upper_left, lower_right, &Formula::default(),
+                                   &Shading::default());
+
+        // The first pixel always samples the upper left corner itself.
+        assert_eq!(pixels[0], expected(upper_left));
+        for row in 0..bounds.1 {
+            for column in 0..bounds.0 {
+                let point = pixel_to_point(bounds, (column, row),
+                                           upper_left, lower_right);
+                assert_eq!(pixels[row * bounds.0 + column], expected(point));
+            }
+        }
+    }
+}
+
+/// Return the fraction of the pixels in an image of the given `bounds` and
+/// corners that escape when iterated according to `formula`.
+fn escaped_fraction(bounds: (usize, usize),
+                    upper_left: Complex<f64>,
+                    lower_right: Complex<f64>,
+                    formula: &Formula)
+    -> f64
+{
+    let mut escaped = 0;
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row),
+                                       upper_left, lower_right);
+            if escape_time(point, formula).is_some() {
+                escaped += 1;
+            }
+        }
+    }
+
+    escaped as f64 / (bounds.0 * bounds.1) as f64
+}
+
+/// Return the bounds of a quick, low-resolution copy of an image with the
+/// given `bounds`: 64 pixels wide, with the same aspect ratio.
+fn sample_bounds(bounds: (usize, usize)) -> (usize, usize) {
+    let width = 64;
+    (width, (width * bounds.1 / bounds.0).max(1))
+}
+
+/// The largest iteration limit `auto_iteration_limit` will consider.
+const MAX_AUTO_ITERATIONS: usize = 4096;
+
+/// Choose an iteration limit at which roughly the fraction `target` of the
+/// pixels in an image of the given `bounds` and corners escape, when iterated
+/// as `formula` says. The limit in `formula` itself is ignored.
+///
+/// Raising the limit can only prove more points to be outside the set, so the
+/// escaped fraction never decreases as the limit grows, and we can
+/// binary-search for the smallest limit that reaches `target`. To keep this
+/// cheap, we search using a low-resolution copy of the image.
+/// If even `MAX_AUTO_ITERATIONS` doesn't reach `target`, return that.
+pub fn auto_iteration_limit(target: f64,
+                            bounds: (usize, usize),
+                            upper_left: Complex<f64>,
+                            lower_right: Complex<f64>,
+                            formula: &Formula)
+    -> usize
+{
+    let sample_bounds = sample_bounds(bounds);
+
+    let (mut low, mut high) = (1, MAX_AUTO_ITERATIONS);
+    while low < high {
+        let middle = Formula { limit: (low + high) / 2, ..*formula };
+        if escaped_fraction(sample_bounds, upper_left, lower_right, &middle) >= target {
+            high = middle.limit;
+        } else {
+            low = middle.limit + 1;
+        }
+    }
+
+    low
+}
+
+#[test]
+fn test_auto_iteration_limit() {
+    let bounds = (1000, 750);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+
+    let limit = auto_iteration_limit(0.7, bounds, upper_left, lower_right,
+                                     &Formula::default());
+    let fraction = |bounds, limit| {
+        let formula = Formula { limit, ..Formula::default() };
+        escaped_fraction(bounds, upper_left, lower_right, &formula)
+    };
+    let sample = (64, 48);
+    assert!(fraction(sample, limit) >= 0.7);
+    assert!(fraction(sample, limit - 1) < 0.7);
+    assert!((fraction(bounds, limit) - 0.7).abs() < 0.05);
+}
+
+/// Return the point in the given area whose neighborhood shows the most
+/// detail.
+///
+/// We render a low-resolution copy of an image with the given `bounds` and
+/// corners, and measure the variance of the escape counts in each pixel's
+/// 3x3 neighborhood; the boundary of the set, where counts change quickly,
+/// has the highest variance. Points are iterated as `formula` says, and those
+/// in the set count as the iteration limit.
+fn most_detailed_point(bounds: (usize, usize),
+                       upper_left: Complex<f64>,
+                       lower_right: Complex<f64>,
+                       formula: &Formula)
+    -> Complex<f64>
+{
+    let bounds = sample_bounds(bounds);
+    let mut counts = vec![0.0; bounds.0 * bounds.1];
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row),
+                                       upper_left, lower_right);
+            counts[row * bounds.0 + column] =
+                escape_time(point, formula).unwrap_or(formula.limit) as f64;
+        }
+    }
+
+    let mut best = ((0, 0), -1.0);
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let neighbors: Vec<f64> =
+                (row.saturating_sub(1)..(row + 2).min(bounds.1))
+                .flat_map(|r| (column.saturating_sub(1)..(column + 2).min(bounds.0))
+                          .map(move |c| (r, c)))
+                .map(|(r, c)| counts[r * bounds.0 + c])
+                .collect();
+            let n = neighbors.len() as f64;
+            let mean = neighbors.iter().sum::<f64>() / n;
+            let variance = neighbors.iter()
+                .map(|count| (count - mean) * (count - mean))
+                .sum::<f64>() / n;
+            if variance > best.1 {
+                best = ((column, row), variance);
+            }
+        }
+    }
+
+    pixel_to_point(bounds, best.0, upper_left, lower_right)
+}
+
+/// Return the corners of a view `factor` times smaller than the area between
+/// `upper_left` and `lower_right`, centered on its most detailed point when
+/// iterated as `formula` says.
+pub fn auto_zoom(factor: f64,
+                 bounds: (usize, usize),
+                 upper_left: Complex<f64>,
+                 lower_right: Complex<f64>,
+                 formula: &Formula)
+    -> (Complex<f64>, Complex<f64>)
+{
+    let center = most_detailed_point(bounds, upper_left, lower_right, formula);
+    let half_diagonal = (lower_right - upper_left) / (2.0 * factor);
+    (center - half_diagonal, center + half_diagonal)
+}
+
+#[test]
+fn test_auto_zoom() {
+    // The only part of the set's boundary in this view lies near its left edge.
+    let bounds = (400, 400);
+    let upper_left = Complex { re: 0.0, im: 2.0 };
+    let lower_right = Complex { re: 4.0, im: -2.0 };
+
+    let (zoomed_upper_left, zoomed_lower_right) =
+        auto_zoom(10.0, bounds, upper_left, lower_right, &Formula::default());
+    let center = (zoomed_upper_left + zoomed_lower_right) / 2.0;
+    assert!(center.re < 0.5);
+    assert!(center.im.abs() < 1.0);
+    assert!((zoomed_lower_right.re - zoomed_upper_left.re - 0.4).abs() < 1e-12);
+    assert!((zoomed_upper_left.im - zoomed_lower_right.im - 0.4).abs() < 1e-12);
+}
+
+/// Convert an 8-bit sRGB-encoded gray level to linear light, from 0 to 1.
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear light level, from 0 to 1, to an 8-bit sRGB-encoded gray
+/// level.
+fn linear_to_srgb(value: f64) -> u8 {
+    let v = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Shrink the image in `pixels`, whose dimensions are given by `bounds`, by
+/// `factor` in each direction, returning the smaller image's pixels. Each of
+/// its pixels is the average of a `factor` by `factor` block of the original.
+///
+/// PNG gray levels are sRGB-encoded, so averaging them directly would make
+/// fine detail come out too dark. Instead, we convert to linear light, take
+/// the average there, and convert back.
+pub fn downsample(pixels: &[u8], bounds: (usize, usize), factor: usize) -> Vec<u8> {
+    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(bounds.0.is_multiple_of(factor) && bounds.1.is_multiple_of(factor));
+
+    let small = (bounds.0 / factor, bounds.1 / factor);
+    (0..small.0 * small.1)
+        .into_par_iter()
+        .map(|i| {
+            let (column, row) = (i % small.0 * factor, i / small.0 * factor);
+            let mut total = 0.0;
+            for y in row..row + factor {
+                for x in column..column + factor {
+                    total += srgb_to_linear(pixels[y * bounds.0 + x]);
+                }
+            }
+            linear_to_srgb(total / (factor * factor) as f64)
+        })
+        .collect()
+}
+
+#[test]
+fn test_downsample() {
+    // Black and white averaged in linear light give a gray of half the
+    // intensity, which sRGB encodes as about 73% of full scale, not 50%.
+    let checkerboard = [0, 255, 0, 255,
+                        255, 0, 255, 0];
+    assert_eq!(downsample(&checkerboard, (4, 2), 2), vec![188, 188]);
+
+    // Solid areas keep their level.
+    for value in 0..=255 {
+        assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        assert_eq!(downsample(&[value; 9], (3, 3), 3), vec![value]);
+    }
+}
+
+/// Shrink the image in `pixels`, whose dimensions are given by `bounds`, to a
+/// thumbnail `width` pixels wide, returning the thumbnail's pixels and
+/// dimensions. The thumbnail keeps the image's proportions, and is never
+/// wider than the image itself.
+///
+/// Unlike `downsample`, the scale needn't be a whole number: each of the
+/// thumbnail's pixels averages the area of the image it covers, counting the
+/// pixels at its edges in proportion to how much of them it covers. As
+/// there, the averaging is done in linear light.
+pub fn thumbnail(pixels: &[u8], bounds: (usize, usize), width: usize)
+    -> (Vec<u8>, (usize, usize))
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(width >= 1 && bounds.0 >= 1 && bounds.1 >= 1);
+
+    let width = width.min(bounds.0);
+    let height = ((bounds.1 * width) as f64 / bounds.0 as f64).round().max(1.0) as usize;
+    let scale = (bounds.0 as f64 / width as f64, bounds.1 as f64 / height as f64);
+
+    // Return the pixels from `start` to `end` along one axis, at `scale`
+    // image pixels per thumbnail pixel, with how much of each is covered.
+    let coverage = |start: usize, scale: f64, limit: usize| {
+        let (from, to) = (start as f64 * scale, (start + 1) as f64 * scale);
+        (from.floor() as usize..(to.ceil() as usize).min(limit))
+            .map(move |i| (i, to.min(i as f64 + 1.0) - from.max(i as f64)))
+    };
+
+    let linear: Vec<f64> = pixels.iter().map(|&p| srgb_to_linear(p)).collect();
+    let small = (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            let (column, row) = (i % width, i / width);
+            let mut total = 0.0;
+            for (y, y_weight) in coverage(row, scale.1, bounds.1) {
+                for (x, x_weight) in coverage(column, scale.0, bounds.0) {
+                    total += linear[y * bounds.0 + x] * x_weight * y_weight;
+                }
+            }
+            linear_to_srgb(total / (scale.0 * scale.1))
+        })
+        .collect();
+    (small, (width, height))
+}
+
+#[test]
+fn test_thumbnail() {
+    // A scale of 2.5 in each direction: a thumbnail pixel covers two whole
+    // pixels and half of a third in each direction.
+    let bounds = (10, 5);
+    let (small, small_bounds) = thumbnail(&[77; 50], bounds, 4);
+    assert_eq!(small_bounds, (4, 2));
+    assert_eq!(small, vec![77; 8]);
+
+    // Where the scale is a whole number, this agrees with `downsample`.
+    let checkerboard: Vec<u8> = (0..36)
+        .map(|i: usize| if (i + i / 6).is_multiple_of(2) { 0 } else { 255 })
+        .collect();
+    assert_eq!(thumbnail(&checkerboard, (6, 6), 2),
+               (downsample(&checkerboard, (6, 6), 3), (2, 2)));
+
+    // A thumbnail pixel that takes in two black pixels and half a white one
+    // is a fifth of the way to white, in linear light.
+    let halves: Vec<u8> = (0..15).map(|i| if i % 5 < 2 { 0 } else { 255 }).collect();
+    let (small, small_bounds) = thumbnail(&halves, (5, 3), 2);
+    assert_eq!(small_bounds, (2, 1));
+    assert_eq!(small[1], 255);
+    assert_eq!(small[0], linear_to_srgb(0.5 / 2.5));
+
+    // The height is rounded, but never zero, and thumbnails don't grow.
+    assert_eq!(thumbnail(&[0; 1000], (1000, 1), 10).1, (10, 1));
+    assert_eq!(thumbnail(&[0; 12], (4, 3), 100).1, (4, 3));
+}
+
+#[test]
+fn test_downscale_antialiasing() {
+    // Antialiasing should soften the jagged edges of the set, which shows up
+    // as smaller jumps between neighboring pixels.
+    let bounds = (48, 36);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+    let roughness = |pixels: &[u8]| -> u64 {
+        pixels.windows(2)
+            .map(|w| (w[0] as i64 - w[1] as i64).unsigned_abs())
+            .sum()
+    };
+
+    let aliased = render_pixels(bounds, upper_left, lower_right,
+                                &Formula::default(), &Shading::default());
+    let big = (bounds.0 * 3, bounds.1 * 3);
+    let antialiased = downsample(&render_pixels(big, upper_left, lower_right,
+                                                &Formula::default(),
+                                                &Shading::default()),
+                                 big, 3);
+    assert_eq!(antialiased.len(), bounds.0 * bounds.1);
+    assert!(roughness(&antialiased) < roughness(&aliased));
+}
+
+/// Apply gamma correction to the gray levels in `pixels`: each level `v`
+/// becomes `255·(v/255)^(1/gamma)`. A `gamma` greater than one brightens the
+/// midtones, while black and white stay as they are.
+pub fn apply_gamma(pixels: &mut [u8], gamma: f64) {
+    assert!(gamma > 0.0);
+
+    let table: Vec<u8> = (0..=255)
+        .map(|v| (255.0 * (v as f64 / 255.0).powf(1.0 / gamma)).round() as u8)
+        .collect();
+    for pixel in pixels {
+        *pixel = table[*pixel as usize];
+    }
+}
+
+#[test]
+fn test_apply_gamma() {
+    let mut pixels = [0, 64, 128, 255];
+    apply_gamma(&mut pixels, 2.2);
+    assert_eq!(pixels, [0, 136, 186, 255]);
+
+    let mut pixels: Vec<u8> = (0..=255).collect();
+    apply_gamma(&mut pixels, 1.0);
+    assert!(pixels.iter().enumerate().all(|(i, &p)| p as usize == i));
+}
+
+/// Blend the edges of the image in `pixels`, whose dimensions are given by
+/// `bounds`, with their mirror images, so that copies of it can be tiled
+/// without visible seams.
+///
+/// Within a border one eighth of the image's width, each pixel is mixed with
+/// the pixel at the mirrored position on the opposite side, by an amount that
+/// grows from nothing at the inner edge of the border to an even mix at the
+/// edge of the image. This makes opposite edges identical, at the cost of
+/// some ghosting near them. The top and bottom edges are treated the same
+/// way.
+pub fn make_seamless(pixels: &mut [u8], bounds: (usize, usize)) {
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let (width, height) = bounds;
+    blend_with_mirror(pixels, width, |i, x| i * width + x,
+                      height);
+    blend_with_mirror(pixels, height, |i, y| y * width + i,
+                      width);
+}
+
+/// Blend `lines` lines of `length` pixels each with their mirror images
+/// near their ends, as described for `make_seamless`. `index(i, j)` gives
+/// the position in `pixels` of the `j`'th pixel of the `i`'th line.
+fn blend_with_mirror<F>(pixels: &mut [u8], length: usize, index: F, lines: usize)
+    where F: Fn(usize, usize) -> usize
+{
+    let border = (length / 8).max(1);
+    let original = pixels.to_vec();
+
+    for i in 0..lines {
+        for j in (0..border).chain(length.saturating_sub(border)..length) {
+            let distance = j.min(length - 1 - j);
+            if distance >= border {
+                continue;
+            }
+            let weight = 0.5 * (1.0 - distance as f64 / border as f64);
+            let own = original[index(i, j)] as f64;
+            let mirrored = original[index(i, length - 1 - j)] as f64;
+            pixels[index(i, j)] =
+                ((1.0 - weight) * own + weight * mirrored).round() as u8;
+        }
+    }
+}
+
+#[test]
+fn test_make_seamless() {
+    let bounds = (40, 30);
+    let mut pixels = render_pixels(bounds,
+                                   Complex { re: -1.20, im: 0.35 },
+                                   Complex { re: -1.0, im: 0.20 },
+                                   &Formula::default(), &Shading::default());
+    let original = pixels.clone();
+
+    make_seamless(&mut pixels, bounds);
+    for row in 0..bounds.1 {
+        assert_eq!(pixels[row * bounds.0], pixels[row * bounds.0 + bounds.0 - 1]);
+    }
+    for column in 0..bounds.0 {
+        assert_eq!(pixels[column], pixels[(bounds.1 - 1) * bounds.0 + column]);
+    }
+
+    // The middle of the image is left alone.
+    let middle = (bounds.1 / 2) * bounds.0 + bounds.0 / 2;
+    assert_eq!(pixels[middle], original[middle]);
+}
+
+/// Estimate the box-counting dimension of the boundary of the set in an
+/// image, given the escape results `escapes` for each of its pixels and its
+/// dimensions `bounds`.
+///
+/// A pixel is on the boundary if it is in the set and its neighbor to the
+/// right or below is not, or vice versa. We lay grids of square boxes over
+/// the image, with sides of 1, 2, 4, ... pixels, count how many boxes
+/// contain a boundary pixel at each size, and fit a line to the logarithm of
+/// the count against the logarithm of the number of boxes per side. The
+/// slope of that line is the estimate. Return `None` if the image has no
+/// boundary or is too small to compare several box sizes.
+pub fn box_dimension(escapes: &[Escape], bounds: (usize, usize)) -> Option<f64> {
+    assert!(escapes.len() == bounds.0 * bounds.1);
+
+    let in_set = |column: usize, row: usize| escapes[row * bounds.0 + column].count.is_none();
+    let mut boundary = vec![false; escapes.len()];
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let here = in_set(column, row);
+            boundary[row * bounds.0 + column] =
+                (column + 1 < bounds.0 && in_set(column + 1, row) != here) ||
+                (row + 1 < bounds.1 && in_set(column, row + 1) != here);
+        }
+    }
+
+    let mut samples = vec![];
+    let mut size = 1;
+    while size * 4 <= bounds.0.min(bounds.1) {
+        let mut boxes = std::collections::HashSet::new();
+        for row in 0..bounds.1 {
+            for column in 0..bounds.0 {
+                if boundary[row * bounds.0 + column] {
+                    boxes.insert((column / size, row / size));
+                }
+            }
+        }
+        if boxes.is_empty() {
+            return None;
+        }
+        samples.push(((1.0 / size as f64).ln(), (boxes.len() as f64).ln()));
+        size *= 2;
+    }
+
+    if samples.len() < 2 {
+        return None;
+    }
+
+    // The least-squares slope of the samples.
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|s| s.0).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| s.1).sum::<f64>() / n;
+    let covariance: f64 = samples.iter().map(|s| (s.0 - mean_x) * (s.1 - mean_y)).sum();
+    let variance: f64 = samples.iter().map(|s| (s.0 - mean_x) * (s.0 - mean_x)).sum();
+    Some(covariance / variance)
+}
+
+#[test]
+fn test_box_dimension() {
+    // The left half is in the set, and the right half escapes: the boundary
+    // is a straight line.
+    let bounds = (64, 64);
+    let escapes: Vec<Escape> = (0..bounds.0 * bounds.1)
+        .map(|i| Escape {
+            count: if i % bounds.0 < bounds.0 / 2 { None } else { Some(1) },
+            ..Escape::default()
+        })
+        .collect();
+    let dimension = box_dimension(&escapes, bounds).unwrap();
+    assert!((dimension - 1.0).abs() < 0.05);
+
+    // An image with no boundary has no dimension to estimate.
+    let escapes = vec![Escape::default(); bounds.0 * bounds.1];
+    assert_eq!(box_dimension(&escapes, bounds), None);
+}
+
+use image::ColorType;
+use image::jpeg::JPEGEncoder;
+use std::fs::File;
+
+/// The kinds of image file we can write.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    Png,
+
+    /// A JPEG file, compressed at the given quality, from 1 to 100.
+    Jpeg(u8),
+
+    /// A GIF file holding a single frame.
+    Gif,
+
+    /// A binary PGM file, as `write_pnm` writes it.
+    Pgm,
+
+    /// A binary PPM file, as `write_pnm` writes it.
+    Ppm,
+}
+
+/// The JPEG quality `FileFormat::from_filename` uses.
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+impl FileFormat {
+    /// Choose a format for the file named `filename` from its extension. A
+    /// file with no extension gets a PNG. If we can't write files with the
+    /// extension given, return an error message listing the ones we can.
+    pub fn from_filename(filename: &str) -> Result<FileFormat, String> {
+        match Path::new(filename).extension() {
+            None => Ok(FileFormat::Png),
+            Some(extension) => FileFormat::from_extension(&extension.to_string_lossy())
+        }
+    }
+
+    /// Choose a format from a filename extension, without the dot, like
+    /// `"png"` or `"jpg"`, ignoring case.
+    pub fn from_extension(extension: &str) -> Result<FileFormat, String> {
+        let extension = extension.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Ok(FileFormat::Png),
+            "jpg" | "jpeg" => Ok(FileFormat::Jpeg(DEFAULT_JPEG_QUALITY)),
+            "gif" => Ok(FileFormat::Gif),
+            "pgm" => Ok(FileFormat::Pgm),
+            "ppm" => Ok(FileFormat::Ppm),
+            _ => Err(format!("can't write .{} files: supported formats are \
+                              .png, .jpg, .jpeg, .gif, .pgm and .ppm", extension))
+        }
+    }
+}
+
+#[test]
+fn test_file_format_from_filename() {
+    assert_eq!(FileFormat::from_filename("mandel.png"), Ok(FileFormat::Png));
+    assert_eq!(FileFormat::from_filename("mandel"), Ok(FileFormat::Png));
+    assert_eq!(FileFormat::from_filename("out/Mandel.JPG"),
+               Ok(FileFormat::Jpeg(DEFAULT_JPEG_QUALITY)));
+    assert_eq!(FileFormat::from_filename("mandel.jpeg"),
+               Ok(FileFormat::Jpeg(DEFAULT_JPEG_QUALITY)));
+    assert_eq!(FileFormat::from_filename("mandel.gif"), Ok(FileFormat::Gif));
+    assert_eq!(FileFormat::from_filename("mandel.ppm"), Ok(FileFormat::Ppm));
+    assert_eq!(FileFormat::from_extension("PGM"), Ok(FileFormat::Pgm));
+    let message = FileFormat::from_filename("mandel.bmp").unwrap_err();
+    assert!(message.contains(".bmp"));
+    assert!(message.contains(".png, .jpg"));
+}
+
+/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to the
+/// file named `filename`, in the format its extension calls for.
+pub fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize))
+    -> Result<(), std::io::Error>
+{
+    let format = FileFormat::from_filename(filename)
+        .map_err(|message| std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                               message))?;
+    write_image_as(filename, pixels, bounds, format)
+}
+
+/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to the
+/// file named `filename`, in the format `format`.
+pub fn write_image_as(filename: &str,
+                      pixels: &[u8],
+                      bounds: (usize, usize),
+                      format: FileFormat)
+    -> Result<(), std::io::Error>
+{
+    let output = BufWriter::new(File::create(filename)?);
+    encode_image(output, pixels, bounds, format, &[])
+}
+
+/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to
+/// `output` in the format `format`. The encoders write in small pieces, so
+/// `output` should be buffered.
+///
+/// If the format is PNG, store each `(keyword, text)` pair in `text` in the
+/// file as well, as `encode_png` does. Other formats ignore `text`.
+pub fn encode_image<W: Write>(mut output: W,
+                              pixels: &[u8],
+                              bounds: (usize, usize),
+                              format: FileFormat,
+                              text: &[(String, String)])
+    -> Result<(), std::io::Error>
+{
+    match format {
+        FileFormat::Png => {
+            encode_png(&mut output, pixels, bounds, png::ColorType::Grayscale,
+                       png::BitDepth::Eight, text)?;
+        }
+        FileFormat::Jpeg(quality) => {
+            let mut encoder = JPEGEncoder::new_with_quality(&mut output, quality);
+            encoder.encode(pixels,
+                           bounds.0 as u32, bounds.1 as u32,
+                           ColorType::Gray(8))?;
+        }
+        FileFormat::Gif => {
+            // A single frame's delay doesn't matter.
+            encode_animation(&mut output, &[pixels], bounds, 1.0)?;
+        }
+        FileFormat::Pgm | FileFormat::Ppm => {
+            write_pnm(&mut output, pixels, bounds, format == FileFormat::Ppm)?;
+        }
+    }
+
+    output.flush()
+}
+
+#[test]
+fn test_encode_image() {
+    let bounds = (8, 4);
+    let pixels: Vec<u8> = (0..32).map(|i| i * 8).collect();
+
+    let text = vec![("Scale".to_string(), "0.001".to_string()),
+                    ("Iteration limit".to_string(), "1000".to_string())];
+    let mut png = Vec::new();
+    encode_image(&mut png, &pixels, bounds, FileFormat::Png, &text).unwrap();
+    let decoded = image::load_from_memory(&png).unwrap().to_luma();
+    assert_eq!(decoded.dimensions(), (8, 4));
+    assert_eq!(decoded.into_raw(), pixels);
+
+    // Walk the PNG's chunks, after its eight-byte signature, collecting the
+    // text chunks. Each chunk is a four-byte big-endian length, a four-byte
+    // type, its data, and a four-byte checksum.
+    let mut found = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let (kind, data) = (&rest[4..8], &rest[8..8 + length]);
+        if kind == b"tEXt" {
+            let nul = data.iter().position(|&b| b == 0).unwrap();
+            found.push((String::from_utf8(data[..nul].to_vec()).unwrap(),
+                        String::from_utf8(data[nul + 1..].to_vec()).unwrap()));
+        }
+        rest = &rest[12 + length..];
+    }
+    assert_eq!(found, text);
+}
+
+/// Write the buffer `samples`, holding an image whose dimensions are given by
+/// `bounds`, to `output` as a PNG of the given color type, grayscale with or
+/// without alpha, whose samples have the given depth. Sixteen-bit samples
+/// must be most significant byte first, and alpha samples must follow the
+/// gray sample for the same pixel.
+///
+/// Store each `(keyword, text)` pair in `text` in a `tEXt` chunk ahead of the
+/// image data, where viewers and tools like `exiftool` can show it. PNG text
+/// is Latin-1, so other characters become question marks.
+pub fn encode_png<W: Write>(output: W,
+                            samples: &[u8],
+                            bounds: (usize, usize),
+                            color: png::ColorType,
+                            depth: png::BitDepth,
+                            text: &[(String, String)])
+    -> Result<(), std::io::Error>
+{
+    use png::HasParameters;
+
+    let latin1 = |s: &str| -> Vec<u8> {
+        s.chars().map(|c| if (c as u32) < 256 { c as u8 } else { b'?' }).collect()
+    };
+
+    let mut encoder = png::Encoder::new(output, bounds.0 as u32, bounds.1 as u32);
+    encoder.set(color).set(depth);
+    let mut writer = encoder.write_header()?;
+    for (keyword, value) in text {
+        let mut chunk = latin1(keyword);
+        chunk.push(0);
+        chunk.extend(latin1(value));
+        writer.write_chunk(*b"tEXt", &chunk)?;
+    }
+    writer.write_image_data(samples)?;
+    Ok(())
+}
+
+/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to
+/// `output` as a binary Netpbm image: a PPM file if `color` is true, or a PGM
+/// file otherwise.
+///
+/// These formats are simple enough to write by hand, and to inspect with a
+/// hex dump: a short text header giving the format, the width and height, and
+/// the maximum sample value, followed by the samples themselves, one byte
+/// each. A PPM file has red, green, and blue samples for each pixel; since
+/// our images are gray, we write each gray level three times.
+pub fn write_pnm<W: Write>(mut output: W,
+                           pixels: &[u8],
+                           bounds: (usize, usize),
+                           color: bool)
+    -> Result<(), std::io::Error>
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let magic = if color { "P6" } else { "P5" };
+    write!(output, "{}\n{} {}\n255\n", magic, bounds.0, bounds.1)?;
+    if color {
+        for &gray in pixels {
+            output.write_all(&[gray, gray, gray])?;
+        }
+    } else {
+        output.write_all(pixels)?;
+    }
+    output.flush()
+}
+
+#[test]
+fn test_write_pnm() {
+    let bounds = (12, 5);
+    let pixels: Vec<u8> = (0..60).map(|i| i * 4).collect();
+
+    let mut ppm = Vec::new();
+    write_pnm(&mut ppm, &pixels, bounds, true).unwrap();
+    let header = b"P6\n12 5\n255\n";
+    assert_eq!(&ppm[..header.len()], &header[..]);
+    assert_eq!(ppm.len(), header.len() + 12 * 5 * 3);
+    assert_eq!(&ppm[header.len() + 3 * 13..][..3], &[52, 52, 52]);
+
+    let mut pgm = Vec::new();
+    write_pnm(&mut pgm, &pixels, bounds, false).unwrap();
+    let header = b"P5\n12 5\n255\n";
+    assert_eq!(&pgm[..header.len()], &header[..]);
+    assert_eq!(&pgm[header.len()..], &pixels[..]);
+}
+
+#[test]
+fn test_write_image_formats() {
+    let bounds = (40, 30);
+    let pixels = render_pixels(bounds,
+                               Complex { re: -2.0, im: 1.0 },
+                               Complex { re: 1.0, im: -1.0 },
+                               &Formula::default(), &Shading::default());
+
+    let directory = std::env::temp_dir();
+    // `image` can't read PGM files, so `test_write_pnm` checks those.
+    for extension in &["png", "jpg", "jpeg", "gif", "ppm"] {
+        let filename = directory.join(format!("mandelbrot-format-{}.{}",
+                                              std::process::id(), extension));
+        let filename = filename.to_str().unwrap();
+        write_image(filename, &pixels, bounds).unwrap();
+
+        let reloaded = image::open(filename).unwrap().to_luma();
+        fs::remove_file(filename).unwrap();
+        assert_eq!(reloaded.dimensions(), (40, 30));
+
+        let errors: Vec<i32> = reloaded.into_raw().iter().zip(&pixels)
+            .map(|(&r, &p)| (r as i32 - p as i32).abs())
+            .collect();
+        let mean_error = errors.iter().sum::<i32>() as f64 / errors.len() as f64;
+        match *extension {
+            "png" => assert!(errors.iter().all(|&e| e == 0)),
+            // `image` reads GIFs and PPMs as RGB, and its conversion back to
+            // gray can round down by one.
+            "gif" | "ppm" => assert!(errors.iter().all(|&e| e <= 1)),
+            // JPEG is lossy, smearing the set's sharp edges, but on the whole
+            // it shouldn't be far off at the default quality.
+            _ => assert!(mean_error < 16.0, "JPEG is off by {} on average", mean_error)
+        }
+    }
+
+    let filename = directory.join(format!("mandelbrot-format-{}.bmp",
+                                          std::process::id()));
+    let error = write_image(filename.to_str().unwrap(), &pixels, bounds).unwrap_err();
+    assert!(error.to_string().contains("supported formats are"));
+    assert!(!filename.exists());
+}
+
+/// What to store in each pixel of the image file we write.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PixelFormat {
+    /// An 8-bit gray level, shaded as the `Shading` directs.
+    #[default]
+    Gray8,
+
+    /// A 16-bit value holding the point's raw escape count, as
+    /// `write_counts_image` writes it.
+    Gray16,
+}
+
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "gray8" => Ok(PixelFormat::Gray8),
+            "gray16" => Ok(PixelFormat::Gray16),
+            _ => Err(format!("unknown pixel format: {}", s))
+        }
+    }
+}
+
+/// Write the escape counts in `escapes`, whose dimensions are given by
+/// `bounds`, to the file named `filename` as a 16-bit grayscale PNG, with no
+/// shading at all.
+///
+/// A point that escaped after `n` iterations gets the value `n + 1`, so
+/// that zero can stand for points in the set. Counts too large to fit are
+/// clamped to 65535.
+pub fn write_counts_image(filename: &str, escapes: &[Escape], bounds: (usize, usize))
+    -> Result<(), std::io::Error>
+{
+    let output = BufWriter::new(File::create(filename)?);
+    encode_counts_image(output, escapes, bounds, &[])
+}
+
+/// Write the escape counts in `escapes` to `output` as a 16-bit grayscale
+/// PNG, as `write_counts_image` does, with the text chunks `text`, as
+/// `encode_png` writes them.
+pub fn encode_counts_image<W: Write>(mut output: W,
+                                     escapes: &[Escape],
+                                     bounds: (usize, usize),
+                                     text: &[(String, String)])
+    -> Result<(), std::io::Error>
+{
+    assert!(escapes.len() == bounds.0 * bounds.1);
+
+    // PNG stores 16-bit samples most significant byte first.
+    let samples: Vec<u8> = escapes.iter()
+        .map(|escape| match escape.count {
+            None => 0,
+            Some(count) => (count + 1).min(u16::MAX as usize) as u16
+        })
+        .flat_map(|value| value.to_be_bytes().to_vec())
+        .collect();
+
+    encode_png(&mut output, &samples, bounds, png::ColorType::Grayscale,
+               png::BitDepth::Sixteen, text)?;
+    output.flush()
+}
+
+#[test]
+fn test_write_counts_image() {
+    use png::HasParameters;
+
+    let bounds = (2, 2);
+    let escapes: Vec<Escape> = [None, Some(0), Some(1000), Some(70000)].iter()
+        .map(|&count| Escape { count, ..Escape::default() })
+        .collect();
+
+    let filename = std::env::temp_dir()
+        .join(format!("mandelbrot-counts-{}.png", std::process::id()));
+    let filename = filename.to_str().unwrap();
+    write_counts_image(filename, &escapes, bounds).unwrap();
+
+    // The `image` crate's decoder scales 16-bit samples down to 8 bits, so
+    // use the `png` crate directly, with no transformations.
+    let mut decoder = png::Decoder::new(File::open(filename).unwrap());
+    decoder.set(png::TRANSFORM_IDENTITY);
+    let (info, mut reader) = decoder.read_info().unwrap();
+    assert_eq!((info.width, info.height), (2, 2));
+    assert_eq!((info.color_type, info.bit_depth),
+               (png::ColorType::Grayscale, png::BitDepth::Sixteen));
+    let mut samples = vec![0; info.buffer_size()];
+    reader.next_frame(&mut samples).unwrap();
+    fs::remove_file(filename).unwrap();
+
+    let values: Vec<u16> = samples.chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    assert_eq!(values, [0, 1, 1001, 65535]);
+}
+
+/// Return an alpha channel for an image whose escape results are `escapes`,
+/// making the points in the set fully transparent and the rest opaque.
+pub fn interior_alpha(escapes: &[Escape]) -> Vec<u8> {
+    escapes.iter()
+        .map(|escape| if escape.count.is_some() { 255 } else { 0 })
+        .collect()
+}
+
+/// Write the gray levels `pixels` of an image whose dimensions are given by
+/// `bounds` to `output` as an 8-bit PNG with the alpha channel `alpha`, such
+/// as `interior_alpha` returns, and the text chunks `text`, as `encode_png`
+/// writes them.
+pub fn encode_image_with_alpha<W: Write>(mut output: W,
+                                         pixels: &[u8],
+                                         alpha: &[u8],
+                                         bounds: (usize, usize),
+                                         text: &[(String, String)])
+    -> Result<(), std::io::Error>
+{
+    assert!(pixels.len() == bounds.0 * bounds.1 && alpha.len() == pixels.len());
+
+    let mut samples = Vec::with_capacity(2 * pixels.len());
+    for (&gray, &alpha) in pixels.iter().zip(alpha) {
+        samples.push(gray);
+        samples.push(alpha);
+    }
+    encode_png(&mut output, &samples, bounds, png::ColorType::GrayscaleAlpha,
+               png::BitDepth::Eight, text)?;
+    output.flush()
+}
+
+#[test]
+fn test_encode_image_with_alpha() {
+    let bounds = (40, 30);
+    let formula = Formula::default();
+    let shading = Shading { interior: InteriorColoring::LastZ, ..Shading::default() };
+    let escapes = render_parallel(bounds,
+                                  Complex { re: -2.0, im: 1.0 },
+                                  Complex { re: 1.0, im: -1.0 },
+                                  &formula);
+    let mut pixels = vec![0; escapes.len()];
+    shade(&mut pixels, &escapes, &formula, 1.0, &shading);
+
+    let mut png = Vec::new();
+    encode_image_with_alpha(&mut png, &pixels, &interior_alpha(&escapes), bounds, &[])
+        .unwrap();
+    let decoded = image::load_from_memory(&png).unwrap().to_rgba();
+    assert_eq!(decoded.dimensions(), (40, 30));
+
+    // Pixels in the set are transparent, whatever their gray, and the rest
+    // are opaque, and keep their gray.
+    let mut transparent = 0;
+    for ((decoded, &gray), escape) in decoded.pixels().zip(&pixels).zip(&escapes) {
+        let expected_alpha = if escape.count.is_some() { 255 } else { 0 };
+        assert_eq!(decoded.data, [gray, gray, gray, expected_alpha]);
+        if expected_alpha == 0 {
+            transparent += 1;
+        }
+    }
+    assert!(transparent > 0 && transparent < escapes.len());
+}
+
+use std::convert::TryFrom;
+use std::io::BufWriter;
+
+/// Write `frames`, each a buffer of gray levels whose dimensions are given by
+/// `bounds`, to the file named `filename` as an animated GIF that shows `fps`
+/// frames per second and loops forever.
+///
+/// GIF images are drawn from a palette of at most 256 colors, so full-color
+/// images have to be quantized to fit. Our gray levels fit exactly: we use a
+/// global palette whose `i`'th entry is the gray level `i`, so each pixel is
+/// its own palette index. GIF frame delays are whole hundredths of a second,
+/// so `fps` is rounded to the nearest rate that allows; and since many
+/// viewers treat delays below two hundredths as a mistake and slow them right
+/// down, we never go faster than 50 frames per second.
+pub fn write_animation<I>(filename: &str, frames: I, bounds: (usize, usize), fps: f64)
+    -> Result<(), std::io::Error>
+    where I: IntoIterator, I::Item: AsRef<[u8]>
+{
+    let output = BufWriter::new(File::create(filename)?);
+    encode_animation(output, frames, bounds, fps)
+}
+
+/// Write `frames` to `output` as an animated GIF, as `write_animation` does.
+pub fn encode_animation<W, I>(mut output: W, frames: I, bounds: (usize, usize), fps: f64)
+    -> Result<(), std::io::Error>
+    where W: Write, I: IntoIterator, I::Item: AsRef<[u8]>
+{
+    let too_large = || std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                           "image too large for a GIF file");
+    let width = u16::try_from(bounds.0).map_err(|_| too_large())?;
+    let height = u16::try_from(bounds.1).map_err(|_| too_large())?;
+    let palette: Vec<u8> = (0..=255).flat_map(|gray| vec![gray; 3]).collect();
+    let delay = (100.0 / fps).round().clamp(2.0, u16::MAX as f64) as u16;
+
+    {
+        let mut encoder = gif::Encoder::new(&mut output, width, height, &palette)?;
+        encoder.write_extension(gif::ExtensionData::Repetitions(gif::Repeat::Infinite))?;
+        for pixels in frames {
+            let pixels = pixels.as_ref();
+            assert!(pixels.len() == bounds.0 * bounds.1);
+            let frame = gif::Frame {
+                delay,
+                width,
+                height,
+                buffer: pixels.into(),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame)?;
+        }
+        // Dropping the encoder writes the GIF trailer.
+    }
+    output.flush()
+}
+
+#[test]
+fn test_write_animation() {
+    use gif::SetParameter;
+
+    let bounds = (5, 3);
+    let frames: Vec<Vec<u8>> = (0..4u8)
+        .map(|n| (0..15).map(|i| i * 17 + n).collect())
+        .collect();
+    let filename = std::env::temp_dir()
+        .join(format!("mandelbrot-animation-{}.gif", std::process::id()));
+    let filename = filename.to_str().unwrap();
+    write_animation(filename, &frames, bounds, 25.0).unwrap();
+
+    let mut decoder = gif::Decoder::new(File::open(filename).unwrap());
+    decoder.set(gif::ColorOutput::Indexed);
+    let mut reader = decoder.read_info().unwrap();
+    assert_eq!((reader.width(), reader.height()), (5, 3));
+    for pixels in &frames {
+        let frame = reader.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.delay, 4);
+        assert_eq!(&frame.buffer[..], &pixels[..]);
+    }
+    assert!(reader.read_next_frame().unwrap().is_none());
+    fs::remove_file(filename).unwrap();
+
+    assert!(encode_animation(Vec::new(), &frames[..0], (70000, 1), 25.0).is_err());
+}
+
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+/// Make sure we'll be able to write an image to `filename` before we spend
+/// time rendering it, returning a message explaining the problem if not. If
+/// `mkdir` is true, create the file's directory if it doesn't exist yet.
+///
+/// An existing file is opened without truncating it, so it is left as it is
+/// until we write the finished image over it. If there is no file, we create
+/// one to be sure we can, and then remove it again, so that a render that
+/// never gets going leaves nothing behind.
+pub fn check_output_path(filename: &str, mkdir: bool) -> Result<(), String> {
+    let directory = match Path::new(filename).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new(".")
+    };
+
+    if !directory.exists() {
+        if !mkdir {
+            return Err(format!("directory {} does not exist \
+                                (use --mkdir to create it)",
+                               directory.display()));
+        }
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("can't create directory {}: {}",
+                                 directory.display(), e))?;
+    } else if !directory.is_dir() {
+        return Err(format!("{} is not a directory", directory.display()));
+    }
+
+    let cant_write = |e: io::Error| format!("can't write to {}: {}", filename, e);
+    if Path::new(filename).exists() {
+        OpenOptions::new().write(true).open(filename).map_err(cant_write)?;
+    } else {
+        OpenOptions::new().write(true).create_new(true).open(filename).map_err(cant_write)?;
+        fs::remove_file(filename).map_err(cant_write)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_output_path() {
+    let directory = std::env::temp_dir()
+        .join(format!("mandelbrot-missing-{}", std::process::id()));
+    let filename = directory.join("mandel.png");
+    let filename = filename.to_str().unwrap();
+
+    let message = check_output_path(filename, false).unwrap_err();
+    assert!(message.contains("does not exist"));
+    assert!(message.contains("--mkdir"));
+    assert!(!directory.exists());
+
+    assert_eq!(check_output_path(filename, true), Ok(()));
+    assert!(directory.is_dir());
+    assert!(!Path::new(filename).exists());
+
+    // An existing file isn't disturbed.
+    fs::write(filename, b"old").unwrap();
+    assert_eq!(check_output_path(filename, false), Ok(()));
+    assert_eq!(fs::read(filename).unwrap(), b"old");
+
+    let message = check_output_path(&format!("{}/inside.png", filename), true)
+        .unwrap_err();
+    assert!(message.contains("not a directory"));
+
+    fs::remove_dir_all(directory).unwrap();
+}
+
+use image::ImageError;
+use std::fmt;
+
+/// Why `count_mismatches` couldn't compare an image with its reference.
+#[derive(Debug)]
+pub enum CompareError {
+    /// The reference image couldn't be opened or decoded.
+    Unreadable(ImageError),
+
+    /// The reference image is `reference` pixels in size, but the image
+    /// compared with it is `render`.
+    WrongSize { reference: (usize, usize), render: (usize, usize) }
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompareError::Unreadable(error) => write!(f, "{}", error),
+            CompareError::WrongSize { reference, render } =>
+                write!(f, "reference image is {}x{}, but the render is {}x{}",
+                       reference.0, reference.1, render.0, render.1)
+        }
+    }
+}
+
+impl From<ImageError> for CompareError {
+    fn from(error: ImageError) -> CompareError {
+        CompareError::Unreadable(error)
+    }
+}
+
+/// Compare the buffer `pixels`, whose dimensions are given by `bounds`, with
+/// the image in the file named `filename`, and return the number of pixels
+/// that differ.
+///
+/// The reference image is converted to grayscale before comparing. If it
+/// can't be read, or its dimensions don't match `bounds`, return an error
+/// saying which.
+pub fn count_mismatches(filename: &str, pixels: &[u8], bounds: (usize, usize))
+    -> Result<usize, CompareError>
+{
+    let reference = image::open(filename)?.to_luma();
+    if reference.dimensions() != (bounds.0 as u32, bounds.1 as u32) {
+        return Err(CompareError::WrongSize {
+            reference: (reference.width() as usize, reference.height() as usize),
+            render: bounds
+        });
+    }
+
+    Ok(reference.into_raw().iter()
+       .zip(pixels)
+       .filter(|(r, p)| r != p)
+       .count())
+}
+
+#[test]
+fn test_count_mismatches() {
+    let bounds = (32, 24);
+    let pixels = render_pixels(bounds,
+                               Complex { re: -1.20, im: 0.35 },
+                               Complex { re: -1.0, im: 0.20 },
+                               &Formula::default(), &Shading::default());
+
+    let directory = std::env::temp_dir();
+    let same = directory.join(format!("mandelbrot-same-{}.png", std::process::id()));
+    let same = same.to_str().unwrap();
+    write_image(same, &pixels, bounds).unwrap();
+    assert_eq!(count_mismatches(same, &pixels, bounds).unwrap(), 0);
+
+    let mut modified = pixels.clone();
+    for i in &[0, 100, 500] {
+        modified[*i] = modified[*i].wrapping_add(1);
+    }
+    let different = directory.join(format!("mandelbrot-different-{}.png",
+                                           std::process::id()));
+    let different = different.to_str().unwrap();
+    write_image(different, &modified, bounds).unwrap();
+    assert_eq!(count_mismatches(different, &pixels, bounds).unwrap(), 3);
+
+    match count_mismatches(same, &pixels, (24, 32)) {
+        Err(CompareError::WrongSize { reference, render }) =>
+            assert_eq!((reference, render), ((32, 24), (24, 32))),
+        other => panic!("expected a size mismatch, got {:?}", other)
+    }
+    let missing = directory.join(format!("mandelbrot-missing-{}.png", std::process::id()));
+    match count_mismatches(missing.to_str().unwrap(), &pixels, bounds) {
+        Err(CompareError::Unreadable(_)) => (),
+        other => panic!("expected an unreadable reference, got {:?}", other)
+    }
+
+    std::fs::remove_file(same).unwrap();
+    std::fs::remove_file(different).unwrap();
+}
+
+use std::io::{self, BufRead, Write};
+
+/// Read points on the complex plane from `input`, one per line in any form
+/// `parse_complex` accepts, and write a line of CSV to `output` for each,
+/// saying whether it seems to be in the Mandelbrot set and, if not, its
+/// escape time, both whole and smoothed as `smooth_count` computes,
+/// iterating each point as `formula` says. Blank lines are skipped.
+///
+/// If a line can't be parsed, return an error naming its line number.
+pub fn classify_points<R, W>(input: R, output: &mut W, formula: &Formula) -> io::Result<()>
+    where R: BufRead, W: Write
+{
+    writeln!(output, "re,im,in_set,escape_time,smooth_escape_time")?;
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let point = parse_complex(line)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("line {}: can't parse point: {}",
+                                                  number + 1, line)))?;
+        let escape = iterate(point, formula);
+        match escape.count {
+            None => writeln!(output, "{},{},true,,", point.re, point.im)?,
+            Some(count) => writeln!(output, "{},{},false,{},{}", point.re, point.im, count,
+                                    smooth_count(count, escape.z, formula))?
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_classify_points() {
+    let input = "-0.5,0\n\n2,2\n";
+    let mut output = vec![];
+    let formula = Formula::default();
+    classify_points(input.as_bytes(), &mut output, &formula).unwrap();
+    let smoothed = smooth_count(1, iterate(Complex { re: 2.0, im: 2.0 }, &formula).z,
+                                &formula);
+    assert_eq!(String::from_utf8(output).unwrap(),
+               format!("re,im,in_set,escape_time,smooth_escape_time\n\
+                        -0.5,0,true,,\n\
+                        2,2,false,1,{}\n", smoothed));
+
+    let mut output = vec![];
+    let error = classify_points("0,0\nbogus\n".as_bytes(), &mut output,
+                                &Formula::default())
+        .unwrap_err();
+    assert!(error.to_string().contains("line 2"));
+}
+
+/// Write the escape counts in `escapes`, an image whose dimensions are given
+/// by `bounds`, to `output` as a grid of numbers, one row of text per row of
+/// pixels, with the columns right-aligned. Points in the set appear as `-`.
+pub fn print_counts<W: Write>(output: &mut W, escapes: &[Escape], bounds: (usize, usize))
+    -> io::Result<()>
+{
+    assert!(escapes.len() == bounds.0 * bounds.1);
+
+    let cells: Vec<String> = escapes.iter()
+        .map(|escape| match escape.count {
+            None => "-".to_string(),
+            Some(count) => count.to_string()
+        })
+        .collect();
+    let width = cells.iter().map(|cell| cell.len()).max().unwrap_or(0);
+    for row in cells.chunks(bounds.0) {
+        let row: Vec<String> = row.iter()
+            .map(|cell| format!("{:>width$}", cell, width = width))
+            .collect();
+        writeln!(output, "{}", row.join(" "))?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_print_counts() {
+    let bounds = (3, 3);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let escapes = render_parallel(bounds, upper_left, lower_right, &Formula::default());
+    let mut output = vec![];
+    print_counts(&mut output, &escapes, bounds).unwrap();
+
+    let text = String::from_utf8(output).unwrap();
+    assert_eq!(text.lines().count(), 3);
+    for (row, line) in text.lines().enumerate() {
+        let cells: Vec<&str> = line.split_whitespace().collect();
+        assert_eq!(cells.len(), 3);
+        for (column, cell) in cells.iter().enumerate() {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            let expected = match escape_time(point, &Formula::default()) {
+                None => "-".to_string(),
+                Some(count) => count.to_string()
+            };
+            assert_eq!(*cell, expected);
+        }
+    }
+
+    // The columns line up.
+    assert_eq!(text, " 1  3  -\n 1 11  -\n 1 11  -\n");
+}
+
+/// Return the escape count of `escape` as a signed number, with -1 for
+/// points in the set, for the data files `write_counts_csv` and
+/// `write_counts_npy` write. Counts too large for an `i32` are clamped.
+fn signed_count(escape: &Escape) -> i32 {
+    match escape.count {
+        None => -1,
+        Some(count) => count.min(i32::MAX as usize) as i32
+    }
+}
+
+/// Write the escape counts in `escapes`, an image whose dimensions are given
+/// by `bounds`, to `output` as CSV: one line per row of pixels, with the
+/// counts separated by commas, and -1 for points in the set.
+pub fn write_counts_csv<W: Write>(output: &mut W, escapes: &[Escape], bounds: (usize, usize))
+    -> io::Result<()>
+{
+    assert!(escapes.len() == bounds.0 * bounds.1);
+
+    for row in escapes.chunks(bounds.0.max(1)) {
+        let row: Vec<String> = row.iter()
+            .map(|escape| signed_count(escape).to_string())
+            .collect();
+        writeln!(output, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Write the escape counts in `escapes`, an image whose dimensions are given
+/// by `bounds`, to `output` as a NumPy `.npy` file, holding an array of
+/// little-endian 32-bit integers with a row for each row of pixels, and -1
+/// for points in the set. `numpy.load` reads it directly.
+///
+/// The format is a magic string and version, the length of the header that
+/// follows, and the header itself: a Python dictionary literal giving the
+/// element type, the order of the elements, and the array's shape, padded
+/// with spaces and a newline so that the data starts on a multiple of
+/// sixty-four bytes. The elements follow.
+pub fn write_counts_npy<W: Write>(output: &mut W, escapes: &[Escape], bounds: (usize, usize))
+    -> io::Result<()>
+{
+    assert!(escapes.len() == bounds.0 * bounds.1);
+
+    let mut header = format!("{{'descr': '<i4', 'fortran_order': False, 'shape': ({}, {}), }}",
+                             bounds.1, bounds.0);
+    let preamble = b"\x93NUMPY\x01\x00";
+    while !(preamble.len() + 2 + header.len() + 1).is_multiple_of(64) {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    output.write_all(preamble)?;
+    output.write_all(&(header.len() as u16).to_le_bytes())?;
+    output.write_all(header.as_bytes())?;
+    for escape in escapes {
+        output.write_all(&signed_count(escape).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_counts_data() {
+    let bounds = (3, 2);
+    let escapes: Vec<Escape> = [Some(0), Some(17), None, Some(255), None, Some(3)].iter()
+        .map(|&count| Escape { count, ..Escape::default() })
+        .collect();
+    let expected = [[0, 17, -1], [255, -1, 3]];
+
+    // The CSV has a line per row, and a number per column.
+    let mut csv = vec![];
+    write_counts_csv(&mut csv, &escapes, bounds).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let rows: Vec<Vec<i32>> = csv.lines()
+        .map(|line| line.split(',').map(|cell| cell.parse().unwrap()).collect())
+        .collect();
+    assert_eq!(rows, expected);
+
+    // The .npy header gives the shape, and the data starts on a 64-byte
+    // boundary.
+    let mut npy = vec![];
+    write_counts_npy(&mut npy, &escapes, bounds).unwrap();
+    assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+    let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+    let data_start = 10 + header_len;
+    assert!(data_start.is_multiple_of(64));
+    let header = std::str::from_utf8(&npy[10..data_start]).unwrap();
+    assert!(header.starts_with("{'descr': '<i4', 'fortran_order': False, 'shape': (2, 3), }"));
+    assert!(header.ends_with('\n'));
+    let values: Vec<i32> = npy[data_start..].chunks(4)
+        .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    assert_eq!(values, [0, 17, -1, 255, -1, 3]);
+}
+
+use std::time::Duration;
+
+/// Return a JSON object summarizing a render: its escape results `escapes`,
+/// the image dimensions `bounds`, the iteration `limit`, and `elapsed`, the
+/// time spent rendering.
+///
+/// The iteration statistics count the iterations spent on each pixel, which
+/// is the full limit for points in the set. The memory figure is an
+/// estimate of the largest buffers: the escape results and the pixels.
+pub fn stats_json(escapes: &[Escape], bounds: (usize, usize), limit: usize,
+                  elapsed: Duration)
+    -> String
+{
+    let total = escapes.len();
+    let escaped = escapes.iter().filter(|escape| escape.count.is_some()).count();
+    let iterations = escapes.iter().map(|escape| escape.count.unwrap_or(limit));
+    let min = iterations.clone().min().unwrap_or(0);
+    let max = iterations.clone().max().unwrap_or(0);
+    let mean = iterations.sum::<usize>() as f64 / total.max(1) as f64;
+    let memory = total * (std::mem::size_of::<Escape>() + 1);
+
+    let fields = [
+        ("width", bounds.0.to_string()),
+        ("height", bounds.1.to_string()),
+        ("iteration_limit", limit.to_string()),
+        ("escaped_fraction", (escaped as f64 / total.max(1) as f64).to_string()),
+        ("min_iterations", min.to_string()),
+        ("mean_iterations", mean.to_string()),
+        ("max_iterations", max.to_string()),
+        ("render_seconds", elapsed.as_secs_f64().to_string()),
+        ("precision_bits", f64::MANTISSA_DIGITS.to_string()),
+        ("memory_bytes", memory.to_string()),
+    ];
+    let fields: Vec<String> = fields.iter()
+        .map(|(name, value)| format!("  \"{}\": {}", name, value))
+        .collect();
+    format!("{{\n{}\n}}\n", fields.join(",\n"))
+}
+
+#[test]
+fn test_stats_json() {
+    let bounds = (40, 30);
+    let escapes = render_parallel(bounds,
+                                  Complex { re: -2.5, im: 1.2 },
+                                  Complex { re: 1.0, im: -1.2 },
+                                  &Formula::default());
+    let json = stats_json(&escapes, bounds, 255, Duration::from_millis(1500));
+    let stats: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(stats["width"], 40);
+    assert_eq!(stats["height"], 30);
+    assert_eq!(stats["iteration_limit"], 255);
+    assert_eq!(stats["render_seconds"], 1.5);
+    assert_eq!(stats["precision_bits"], 53);
+    let fraction = stats["escaped_fraction"].as_f64().unwrap();
+    assert!(0.5 < fraction && fraction < 1.0);
+    assert_eq!(stats["min_iterations"], 1);
+    assert_eq!(stats["max_iterations"], 255);
+    let mean = stats["mean_iterations"].as_f64().unwrap();
+    assert!(1.0 < mean && mean < 255.0);
+    assert!(stats["memory_bytes"].as_u64().unwrap() >= 1200);
+}
+
+/// Render an image of the Mandelbrot set as `render_parallel` would, a band
+/// of rows at a time, calling `after_band` with the results so far after
+/// each band, so that it can save them. `done` holds the results for the
+/// image's first rows, if they have already been computed, perhaps by an
+/// earlier call that was interrupted; we render only the rows after those.
+///
+/// Each point is computed exactly as `render_parallel` computes it, so the
+/// result is the same however many times the render is interrupted and
+/// resumed.
+pub fn render_resumable<F>(bounds: (usize, usize),
+                           upper_left: Complex<f64>,
+                           lower_right: Complex<f64>,
+                           formula: &Formula,
+                           done: Vec<Escape>,
+                           mut after_band: F)
+    -> Vec<Escape>
+    where F: FnMut(&[Escape])
+{
+    let (width, height) = bounds;
+    assert!(width > 0 && done.len().is_multiple_of(width) && done.len() <= width * height);
+
+    // Bands of about sixty-four thousand pixels give each thread plenty to
+    // do, while still saving often on large images.
+    let band_rows = (65536 / width).max(1);
+    let mut escapes = done;
+    escapes.reserve_exact(width * height - escapes.len());
+    let mut top = escapes.len() / width;
+    while top < height {
+        let rows = band_rows.min(height - top);
+        escapes.extend(render_tile(bounds, (0, top, width, rows),
+                                   upper_left, lower_right, formula));
+        top += rows;
+        after_band(&escapes);
+    }
+    escapes
+}
+
+/// The first line of every checkpoint file.
+const CHECKPOINT_MAGIC: &[u8] = b"mandelbrot checkpoint 1\n";
+
+/// Write a checkpoint holding the partial render results `escapes` to
+/// `output`, labeled with `description`, which should identify the render
+/// they belong to, so a resumed render can check it's picking up the right
+/// one. The description must not contain a newline.
+///
+/// The file is the `CHECKPOINT_MAGIC` line, the description and a newline,
+/// the number of results as a little-endian `u64`, and then each result in
+/// turn: its escape count as a `u64`, with `u64::MAX` for points in the set,
+/// followed by its other fields as little-endian `f64` values.
+pub fn write_checkpoint<W: Write>(mut output: W, description: &str, escapes: &[Escape])
+    -> io::Result<()>
+{
+    assert!(!description.contains('\n'));
+    output.write_all(CHECKPOINT_MAGIC)?;
+    output.write_all(description.as_bytes())?;
+    output.write_all(b"\n")?;
+    output.write_all(&(escapes.len() as u64).to_le_bytes())?;
+    for escape in escapes {
+        let count = escape.count.map_or(u64::MAX, |count| count as u64);
+        output.write_all(&count.to_le_bytes())?;
+        for &value in &[escape.z.re, escape.z.im, escape.distance,
+                        escape.normal.re, escape.normal.im, escape.trap_distance] {
+            output.write_all(&value.to_le_bytes())?;
+        }
+    }
+    output.flush()
+}
+
+/// Read a checkpoint written by `write_checkpoint` from `input`, returning
+/// its description and the render results it holds.
+pub fn read_checkpoint<R: BufRead>(mut input: R) -> io::Result<(String, Vec<Escape>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData,
+                                                 message.to_string());
+
+    let mut magic = vec![0; CHECKPOINT_MAGIC.len()];
+    input.read_exact(&mut magic)?;
+    if magic != CHECKPOINT_MAGIC {
+        return Err(invalid("not a checkpoint file"));
+    }
+    let mut description = String::new();
+    input.read_line(&mut description)?;
+    if description.pop() != Some('\n') {
+        return Err(invalid("checkpoint file is truncated"));
+    }
+
+    fn read_word<R: BufRead>(input: &mut R) -> io::Result<[u8; 8]> {
+        let mut word = [0; 8];
+        input.read_exact(&mut word)?;
+        Ok(word)
+    }
+    let len = u64::from_le_bytes(read_word(&mut input)?);
+    let mut escapes = Vec::new();
+    for _ in 0..len {
+        let count = match u64::from_le_bytes(read_word(&mut input)?) {
+            u64::MAX => None,
+            count => Some(count as usize),
+        };
+        let mut values = [0.0; 6];
+        for value in &mut values {
+            *value = f64::from_le_bytes(read_word(&mut input)?);
+        }
+        escapes.push(Escape {
+            count,
+            z: Complex { re: values[0], im: values[1] },
+            distance: values[2],
+            normal: Complex { re: values[3], im: values[4] },
+            trap_distance: values[5],
+        });
+    }
+    Ok((description, escapes))
+}
+
+#[test]
+fn test_checkpoint_resume() {
+    let bounds = (300, 400);
+    let upper_left = Complex { re: -2.0, im: 1.5 };
+    let lower_right = Complex { re: 1.0, im: -2.5 };
+    let formula = Formula { estimate_distance: true, ..Formula::default() };
+    let whole = render_parallel(bounds, upper_left, lower_right, &formula);
+
+    // Render in bands, saving a checkpoint once half the image is done, as
+    // if the render had been interrupted then.
+    let mut checkpoint = Vec::new();
+    let resumed = render_resumable(bounds, upper_left, lower_right, &formula, vec![],
+                                   |escapes| {
+        if checkpoint.is_empty() && escapes.len() >= whole.len() / 2 {
+            write_checkpoint(&mut checkpoint, "test render", escapes).unwrap();
+        }
+    });
+    assert_eq!(resumed, whole);
+
+    let (description, done) = read_checkpoint(&checkpoint[..]).unwrap();
+    assert_eq!(description, "test render");
+    assert!(done.len() >= whole.len() / 2 && done.len() < whole.len());
+    assert_eq!(done[..], whole[..done.len()]);
+
+    // Resuming from the checkpoint gives exactly what rendering it all at
+    // once did.
+    let resumed = render_resumable(bounds, upper_left, lower_right, &formula, done,
+                                   |_| {});
+    assert_eq!(resumed, whole);
+
+    assert!(read_checkpoint(&b"mandelbrot picture\n"[..]).is_err());
+    assert!(read_checkpoint(&checkpoint[..checkpoint.len() - 1]).is_err());
+}
@@ -1,195 +1,2298 @@
+//! The command-line interface to the `mandelbrot` library.
+
 #![warn(rust_2018_idioms)]
+#![forbid(unsafe_code)]
 #![allow(elided_lifetimes_in_paths)]
 
+use mandelbrot::{apply_gamma, auto_iteration_limit, auto_zoom, box_dimension, buddhabrot,
+                 check_aspect, check_output_path, classify_points, cost_heatmap,
+                 count_mismatches, count_shades, dither_smooth, downsample,
+                 encode_animation, encode_counts_image, encode_image,
+                 encode_image_with_alpha, find_location, interior_alpha, make_seamless,
+                 parse_complex, parse_pair, pixel_to_point, print_counts,
+                 read_checkpoint, render_parallel_stoppable, render_preview,
+                 render_resumable, render_rotated, render_tile, rotated_pixel_to_point,
+                 shade, shade_hits, stats_json, thumbnail, view_corners,
+                 write_checkpoint, write_counts_csv, write_counts_npy, write_image};
+use mandelbrot::{ColorMode, CompareError, CycleMode, Escape, FileFormat, Formula,
+                 Fractal, InteriorColoring, Location, Normalization, PixelFormat,
+                 Shading, Trap, DEFAULT_JPEG_QUALITY, DEFAULT_VIEW_WIDTH, LOCATIONS};
+#[cfg(test)]
+use mandelbrot::render_parallel;
 use num::Complex;
-use rayon::prelude::*;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
-/// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
-/// iterations to decide.
-///
-/// If `c` is not a member, return `Some(i)`, where `i` is the number of
-/// iterations it took for `c` to leave the circle of radius two centered on the
-/// origin. If `c` seems to be a member (more precisely, if we reached the
-/// iteration limit without being able to prove that `c` is not a member),
-/// return `None`.
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
+/// How much `--auto-iterations` raises the iteration limit each time the view
+/// is half as wide.
+const ITERATIONS_PER_ZOOM_DOUBLING: f64 = 64.0;
+
+/// Return the iteration limit `--auto-iterations` uses for the view from
+/// `upper_left` to `lower_right`: the default limit, plus
+/// `ITERATIONS_PER_ZOOM_DOUBLING` for each time the view has been halved in
+/// width from `DEFAULT_VIEW_WIDTH`. Points near the boundary take longer to
+/// escape the deeper we look, roughly in proportion to the zoom's logarithm.
+fn zoom_iteration_limit(upper_left: Complex<f64>, lower_right: Complex<f64>) -> usize {
+    let doublings = (DEFAULT_VIEW_WIDTH / (lower_right.re - upper_left.re)).log2().max(0.0);
+    Formula::default().limit + (doublings * ITERATIONS_PER_ZOOM_DOUBLING).round() as usize
+}
+
+#[test]
+fn test_zoom_iteration_limit() {
+    let args = vec!["--auto-iterations".to_string()];
+    assert!(parse_args(&args).unwrap().0.auto_iterations);
+
+    let center = Complex { re: -0.75, im: 0.0 };
+    let (upper_left, lower_right) = view_corners(center, DEFAULT_VIEW_WIDTH, (300, 200), 1.0);
+    let shallow = zoom_iteration_limit(upper_left, lower_right);
+    assert_eq!(shallow, Formula::default().limit);
+
+    // A thousandfold zoom is about ten halvings deeper.
+    let (upper_left, lower_right) = view_corners(center, DEFAULT_VIEW_WIDTH / 1000.0,
+                                                 (300, 200), 1.0);
+    let deep = zoom_iteration_limit(upper_left, lower_right);
+    assert_eq!(deep, shallow + 638);
+
+    // Zooming out doesn't lower the limit.
+    let (upper_left, lower_right) = view_corners(center, 4.0 * DEFAULT_VIEW_WIDTH,
+                                                 (300, 200), 1.0);
+    assert_eq!(zoom_iteration_limit(upper_left, lower_right), shallow);
+}
+
+#[test]
+fn test_center() {
+    let args = vec!["--center".to_string(), "-0.743+0.126i".to_string()];
+    let center = parse_args(&args).unwrap().0.center.unwrap();
+    let (upper_left, lower_right) = view_corners(center, DEFAULT_VIEW_WIDTH, (300, 200), 1.0);
+    assert!((upper_left - Complex { re: -2.243, im: 1.126 }).norm() < 1e-12);
+    assert!((lower_right - Complex { re: 0.757, im: -0.874 }).norm() < 1e-12);
+
+    let args = vec!["--center".to_string(), "-0.743,0.126".to_string()];
+    assert_eq!(parse_args(&args).unwrap().0.center, Some(center));
+    let args = vec!["--center".to_string(), "middle".to_string()];
+    assert!(parse_args(&args).is_err());
+}
+
+#[test]
+fn test_zoom() {
+    let args = vec!["--zoom".to_string(), "2".to_string()];
+    let zoom = parse_args(&args).unwrap().0.zoom.unwrap();
+    let center = Complex { re: -0.75, im: 0.0 };
+    let (upper_left, lower_right) = view_corners(center, DEFAULT_VIEW_WIDTH / zoom,
+                                                 (300, 200), 1.0);
+    assert_eq!(lower_right.re - upper_left.re, DEFAULT_VIEW_WIDTH / 2.0);
+    assert_eq!(upper_left.im - lower_right.im, 1.0);
+
+    let location = find_location("mini-mandelbrot").unwrap();
+    let (upper_left, lower_right) = location.corners((300, 200), 1000.0, 1.0);
+    assert!((lower_right.re - upper_left.re - location.width / 1000.0).abs() < 1e-15);
+
+    for zoom in &["0", "-2", "inf", "x"] {
+        let args = vec!["--zoom".to_string(), zoom.to_string()];
+        assert!(parse_args(&args).is_err());
+    }
+}
+
+/// Whether to insist that the image's pixels be square.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Aspect {
+    /// Keep the complex plane's proportions, so circles stay round.
+    #[default]
+    Preserve,
+
+    /// Let the corners given on the command line stretch the image.
+    Stretch,
+}
+
+impl FromStr for Aspect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "preserve" => Ok(Aspect::Preserve),
+            "stretch" => Ok(Aspect::Stretch),
+            _ => Err(format!("unknown aspect: {}", s))
         }
-        z = z * z + c;
     }
+}
+
+#[test]
+fn test_pixel_aspect() {
+    let args = vec!["--pixel-aspect".to_string(), "2:1".to_string()];
+    let pixel_aspect = parse_args(&args).unwrap().0.pixel_aspect.unwrap();
+    assert_eq!(pixel_aspect, 2.0);
 
-    None
+    let bounds = (300, 200);
+    let center = Complex { re: -0.75, im: 0.0 };
+    let (upper_left, lower_right) = view_corners(center, DEFAULT_VIEW_WIDTH, bounds,
+                                                 pixel_aspect);
+    let origin = pixel_to_point(bounds, (0, 0), upper_left, lower_right);
+    let step_re = pixel_to_point(bounds, (1, 0), upper_left, lower_right) - origin;
+    let step_im = pixel_to_point(bounds, (0, 1), upper_left, lower_right) - origin;
+    assert_eq!(step_re.im, 0.0);
+    assert_eq!(step_im.re, 0.0);
+    assert!((step_re.re - 0.01).abs() < 1e-12);
+    assert!((step_im.im + 0.005).abs() < 1e-12);
+    assert_eq!(check_aspect(bounds, upper_left, lower_right, pixel_aspect), Ok(()));
+
+    for bad in &["2", "2:0", "0:1", "-1:1", "2:x"] {
+        let args = vec!["--pixel-aspect".to_string(), bad.to_string()];
+        assert!(parse_args(&args).is_err());
+    }
 }
 
-use std::str::FromStr;
+/// The fewest representable `f64` values a pixel must span, along either
+/// axis, for the image to be drawn faithfully. Below this, rounding the
+/// pixels' points to the nearest `f64` visibly shifts them, so the image
+/// turns blocky; below one, neighboring pixels land on the very same point.
+const MIN_ULPS_PER_PIXEL: f64 = 4.0;
 
-/// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0,0.5"`.
-///
-/// Specifically, `s` should have the form <left><sep><right>, where <sep> is
-/// the character given by the `separator` argument, and <left> and <right> are both
-/// strings that can be parsed by `T::from_str`.
+/// Return the gap between `x` and the next `f64` further from zero: the
+/// finest distinction `f64` can draw near `x`.
+fn ulp(x: f64) -> f64 {
+    let x = x.abs();
+    f64::from_bits(x.to_bits() + 1) - x
+}
+
+/// Check that `f64` has the precision to tell the pixels of an image `bounds`
+/// in size, from `upper_left` to `lower_right`, apart, returning a message
+/// saying how far short it falls if not. Zooming in far enough on any point
+/// but the origin eventually runs out of digits.
+fn check_precision(bounds: (usize, usize),
+                   upper_left: Complex<f64>,
+                   lower_right: Complex<f64>)
+    -> Result<(), String>
+{
+    let pixel_width = (lower_right.re - upper_left.re).abs() / bounds.0 as f64;
+    let pixel_height = (upper_left.im - lower_right.im).abs() / bounds.1 as f64;
+    let ulps = f64::min(pixel_width / ulp(upper_left.re).max(ulp(lower_right.re)),
+                        pixel_height / ulp(upper_left.im).max(ulp(lower_right.im)));
+    if ulps >= MIN_ULPS_PER_PIXEL {
+        return Ok(());
+    }
+    Err(format!("the pixels are only {:.2} f64 steps apart, too close for f64 to \
+                 tell them apart reliably; the image will look blocky", ulps))
+}
+
+#[test]
+fn test_check_precision() {
+    // A view 1e-15 wide near -0.75 is finer than f64 can resolve: the values
+    // there are about 1.1e-16 apart, so 100 pixels share only nine of them.
+    let upper_left = Complex { re: -0.75, im: 0.1 };
+    let tiny = Complex { re: 1e-15, im: -1e-15 };
+    let message = check_precision((100, 100), upper_left, upper_left + tiny).unwrap_err();
+    assert!(message.contains("0.09"), "{}", message);
+
+    // The same view around the origin, where `f64` is far finer, is fine, as
+    // is an ordinary deep zoom.
+    let origin = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(check_precision((100, 100), origin, origin + tiny), Ok(()));
+    assert_eq!(check_precision((1000, 1000), upper_left,
+                               upper_left + tiny * 1e4), Ok(()));
+    assert_eq!(check_precision((300, 200), Complex { re: -2.0, im: 1.0 },
+                               Complex { re: 1.0, im: -1.0 }), Ok(()));
+    assert_eq!(ulp(1.0), f64::EPSILON);
+}
+
+/// Settings given by options on the command line, preceding the positional
+/// arguments.
+#[derive(Default)]
+struct Options {
+    /// If set, choose the iteration limit so that about this fraction of the
+    /// image's pixels escape.
+    auto_iterations_target: Option<f64>,
+
+    /// Whether to raise the iteration limit with the view's magnification.
+    auto_iterations: bool,
+
+    /// If set, zoom in by this factor on the most detailed part of the view.
+    auto_zoom: Option<f64>,
+
+    /// If set, turn the view this many degrees counterclockwise about its
+    /// center.
+    rotate: Option<f64>,
+
+    /// If set, print the point this pixel of the image shows, instead of
+    /// rendering it.
+    query_pixel: Option<(usize, usize)>,
+
+    /// If set, draw only the pixels from the first column and row given up
+    /// to, but not including, the second.
+    roi: Option<(usize, usize, usize, usize)>,
+
+    /// Whether to blend the image's edges so that it tiles seamlessly.
+    seamless: bool,
+
+    /// Whether to dither smoothly shaded images, to hide banding.
+    dither: bool,
+
+    /// How to turn escape times into gray levels.
+    shading: Shading,
+
+    /// Whether to print an estimate of the boundary's box-counting dimension.
+    box_dimension: bool,
+
+    /// If set, also write a heat map of the iterations spent on each pixel
+    /// to this file.
+    cost_heatmap: Option<String>,
+
+    /// If set, draw the bands between the first this many lemniscates,
+    /// instead of escape times.
+    lemniscate: Option<usize>,
+
+    /// If set, compare the image with the one in this file, and exit with an
+    /// error if they differ.
+    check_against: Option<String>,
+
+    /// If set, classify the points listed in this file instead of rendering
+    /// an image.
+    classify_points: Option<String>,
+
+    /// If set, write a strip showing the gray for each escape count to this
+    /// file instead of rendering an image.
+    palette_preview: Option<String>,
+
+    /// If set, print each pixel's escape count to standard output.
+    print_counts: bool,
+
+    /// If set, also write a thumbnail of the image this many pixels wide.
+    thumbnail: Option<usize>,
+
+    /// If set, draw a Buddhabrot, showing where escaping orbits go, rather
+    /// than shading points by how they escape.
+    buddhabrot: bool,
+
+    /// If set, create the output file's directory if it doesn't exist.
+    mkdir: bool,
+
+    /// If set, apply gamma correction with this exponent to the image.
+    gamma: Option<f64>,
+
+    /// If set, write statistics about the render to this file, as JSON.
+    stats_json: Option<String>,
+
+    /// If set, write each pixel's escape count to this file, as CSV or as a
+    /// NumPy `.npy` file, depending on its extension.
+    data_out: Option<String>,
+
+    /// How to iterate points.
+    formula: Formula,
+
+    /// If set, render the image at this many times its size in each
+    /// direction, and then shrink it down, to smooth out jagged edges.
+    downscale: Option<usize>,
+
+    /// If set, take the view and iteration limit from this location, unless
+    /// the corners are given explicitly.
+    location: Option<&'static Location>,
+
+    /// If set, show a view of the default width centered on this point,
+    /// instead of taking the corners from the command line.
+    center: Option<Complex<f64>>,
+
+    /// If set, magnify the `--center` or `--location` view this many times.
+    zoom: Option<f64>,
+
+    /// Whether to list the known locations instead of rendering anything.
+    list_locations: bool,
+
+    /// If set, render this many frames of a zoom animation, instead of a
+    /// single image.
+    frames: Option<usize>,
+
+    /// How much to scale the view by from one frame to the next. If unset,
+    /// use `DEFAULT_ZOOM_FACTOR`.
+    zoom_factor: Option<f64>,
+
+    /// Whether to report the render's progress on standard error.
+    verbose: bool,
+
+    /// Whether to refuse to draw views too fine for `f64`, rather than just
+    /// warning about them.
+    strict: bool,
+
+    /// How many frames per second an animated GIF should show. If unset, use
+    /// `DEFAULT_FPS`.
+    fps: Option<f64>,
+
+    /// What to store in each pixel of the image file.
+    format: PixelFormat,
+
+    /// If set, write the image in this format, whatever FILE's extension.
+    file_format: Option<FileFormat>,
+
+    /// Whether to reject corners that would give the image non-square
+    /// pixels, or pixels not of the shape `pixel_aspect` gives.
+    aspect: Aspect,
+
+    /// If set, the width over the height of the pixels of the display the
+    /// image is meant for. Otherwise, pixels are square.
+    pixel_aspect: Option<f64>,
+
+    /// How many threads to render with. If unset or zero, use one per core,
+    /// as `thread_count` decides.
+    threads: Option<usize>,
+
+    /// If set, draw the image as square tiles this many pixels on a side,
+    /// each written to its own file, instead of all at once.
+    tile_size: Option<usize>,
+
+    /// If set, save the render's progress to a checkpoint file this often,
+    /// so that it can be resumed if it's interrupted.
+    checkpoint_interval: Option<Duration>,
+
+    /// If set, resume the render saved in this checkpoint file, instead of
+    /// starting from scratch.
+    resume: Option<String>,
+
+    /// Whether to make the points in the set transparent, rather than
+    /// shading them.
+    transparent_interior: bool,
+
+    /// Whether to write a quick, blocky preview of the image to FILE before
+    /// rendering the image itself.
+    preview: bool,
+
+    /// The quality to compress JPEG files at. If unset, use
+    /// `DEFAULT_JPEG_QUALITY`.
+    jpeg_quality: Option<u8>,
+}
+
+/// How much each frame of a zoom animation scales the view by, unless the
+/// `--zoom-factor` option says otherwise.
+const DEFAULT_ZOOM_FACTOR: f64 = 0.9;
+
+/// How many frames per second an animated GIF shows, unless the `--fps`
+/// option says otherwise.
+const DEFAULT_FPS: f64 = 25.0;
+
+/// How many points along each side of a pixel `--buddhabrot` follows the
+/// orbits of.
+const BUDDHABROT_SAMPLES: usize = 4;
+
+/// How many pixels across each of a `--preview`'s blocks is.
+const PREVIEW_STEP: usize = 8;
+
+/// Return the name of frame number `frame` of an animation whose frames are
+/// named after `filename`: for example, frame 12 of `zoom.png` is
+/// `zoom-0012.png`.
+fn frame_filename(filename: &str, frame: usize) -> String {
+    suffixed_filename(filename, &format!("{:04}", frame))
+}
+
+/// Return the name of the tile at `column` and `row` of an image drawn in
+/// tiles, named after `filename`: for example, the tile in column 3 and row
+/// 0 of `big.png` is `big-3-0.png`.
+fn tile_filename(filename: &str, column: usize, row: usize) -> String {
+    suffixed_filename(filename, &format!("{}-{}", column, row))
+}
+
+/// Return `filename` with `-` and `suffix` added to its stem, ahead of its
+/// extension.
+fn suffixed_filename(filename: &str, suffix: &str) -> String {
+    let path = std::path::Path::new(filename);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let mut name = format!("{}-{}", stem, suffix);
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        name = format!("{}.{}", name, extension);
+    }
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_frame_filename() {
+    assert_eq!(frame_filename("zoom.png", 1), "zoom-0001.png");
+    assert_eq!(frame_filename("out/zoom.png", 12), "out/zoom-0012.png");
+    assert_eq!(frame_filename("stats.json", 345), "stats-0345.json");
+    assert_eq!(frame_filename("frames", 10000), "frames-10000");
+    assert_eq!(tile_filename("out/big.png", 3, 0), "out/big-3-0.png");
+    assert_eq!(thumbnail_filename("out/zoom-0012.png"), "out/zoom-0012-thumb.png");
+}
+
+/// Return the name of the thumbnail of the image `filename`: for example,
+/// the thumbnail of `mandel.png` is `mandel-thumb.png`.
+fn thumbnail_filename(filename: &str) -> String {
+    suffixed_filename(filename, "thumb")
+}
+
+/// Parse the command-line arguments `args`, not including the program name,
+/// into `Options` and a vector of the remaining positional arguments.
 ///
-/// If `s` has the proper form, return `Some<(x, y)>`. If it doesn't parse
-/// correctly, return `None`.
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
-    match s.find(separator) {
-        None => None,
-        Some(index) => {
-            match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-                (Ok(l), Ok(r)) => Some((l, r)),
-                _ => None
+/// Most options take the form `--name VALUE`; a few, like `--seamless`, are
+/// flags that take no value. If an option is unrecognized or its value is
+/// missing or malformed, return an error message describing the problem.
+fn parse_args(args: &[String]) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg.clone());
+            continue;
+        }
+
+        match arg.as_str() {
+            "--auto-iterations-target" => {
+                let value = option_value(arg, args.next())?;
+                let target = f64::from_str(value).ok()
+                    .filter(|&t| t > 0.0 && t <= 1.0)
+                    .ok_or("--auto-iterations-target must be a fraction \
+                            greater than 0 and at most 1")?;
+                options.auto_iterations_target = Some(target);
+            }
+            "--interior-coloring" => {
+                let value = option_value(arg, args.next())?;
+                options.shading.interior = InteriorColoring::from_str(value)?;
+            }
+            "--set-color" => {
+                let value = option_value(arg, args.next())?;
+                options.shading.set_color = u8::from_str(value).ok()
+                    .ok_or("--set-color must be a gray level from 0 to 255")?;
+            }
+            "--auto-zoom" => {
+                let value = option_value(arg, args.next())?;
+                let factor = f64::from_str(value).ok()
+                    .filter(|&f| f >= 1.0)
+                    .ok_or("--auto-zoom must be a factor of at least 1")?;
+                options.auto_zoom = Some(factor);
+            }
+            "--query-pixel" => {
+                let value = option_value(arg, args.next())?;
+                let pixel = parse_pair(value, ',')
+                    .ok_or("--query-pixel must be a COLUMN,ROW pair")?;
+                options.query_pixel = Some(pixel);
+            }
+            "--roi" => {
+                let value = option_value(arg, args.next())?;
+                let numbers: Result<Vec<usize>, _> = value.split(',')
+                    .map(usize::from_str)
+                    .collect();
+                match numbers.as_deref() {
+                    Ok(&[x0, y0, x1, y1]) => options.roi = Some((x0, y0, x1, y1)),
+                    _ => return Err("--roi must be four pixel coordinates, \
+                                     X0,Y0,X1,Y1".to_string())
+                }
+            }
+            "--rotate" => {
+                let value = option_value(arg, args.next())?;
+                let degrees = f64::from_str(value).ok()
+                    .filter(|d| d.is_finite())
+                    .ok_or("--rotate must be a number of degrees")?;
+                options.rotate = Some(degrees);
+            }
+            "--seamless" => options.seamless = true,
+            "--auto-iterations" => options.auto_iterations = true,
+            "--dither" => options.dither = true,
+            "--transparent-interior" => options.transparent_interior = true,
+            "--print-counts" => options.print_counts = true,
+            "--buddhabrot" => options.buddhabrot = true,
+            "--mkdir" => options.mkdir = true,
+            "--box-dimension" => options.box_dimension = true,
+            "--verbose" => options.verbose = true,
+            "--strict" => options.strict = true,
+            "--preview" => options.preview = true,
+            "--classify-points" => {
+                let value = option_value(arg, args.next())?;
+                options.classify_points = Some(value.to_string());
+            }
+            "--palette-preview" => {
+                let value = option_value(arg, args.next())?;
+                options.palette_preview = Some(value.to_string());
+            }
+            "--location" => {
+                let value = option_value(arg, args.next())?;
+                if value == "list" {
+                    options.list_locations = true;
+                } else {
+                    options.location = Some(find_location(value)?);
+                }
+            }
+            "--center" => {
+                let value = option_value(arg, args.next())?;
+                let center = parse_complex(value)
+                    .ok_or("--center must be a point on the complex plane")?;
+                options.center = Some(center);
+            }
+            "--zoom" => {
+                let value = option_value(arg, args.next())?;
+                let zoom = f64::from_str(value).ok()
+                    .filter(|&z| z > 0.0 && z.is_finite())
+                    .ok_or("--zoom must be a positive number")?;
+                options.zoom = Some(zoom);
+            }
+            "--frames" => {
+                let value = option_value(arg, args.next())?;
+                let frames = usize::from_str(value).ok()
+                    .filter(|&n| n >= 1)
+                    .ok_or("--frames must be a positive integer")?;
+                options.frames = Some(frames);
+            }
+            "--format" => {
+                // Either a kind of pixel, or a kind of file.
+                let value = option_value(arg, args.next())?;
+                match PixelFormat::from_str(value) {
+                    Ok(format) => options.format = format,
+                    Err(_) => options.file_format = Some(FileFormat::from_extension(value)?)
+                }
+            }
+            "--jpeg-quality" => {
+                let value = option_value(arg, args.next())?;
+                let quality = u8::from_str(value).ok()
+                    .filter(|&q| (1..=100).contains(&q))
+                    .ok_or("--jpeg-quality must be a number from 1 to 100")?;
+                options.jpeg_quality = Some(quality);
+            }
+            "--aspect" => {
+                let value = option_value(arg, args.next())?;
+                options.aspect = Aspect::from_str(value)?;
+            }
+            "--pixel-aspect" => {
+                let value = option_value(arg, args.next())?;
+                let (width, height) = parse_pair::<f64>(value, ':')
+                    .filter(|&(w, h): &(f64, f64)| w > 0.0 && h > 0.0 && (w / h).is_normal())
+                    .ok_or("--pixel-aspect must be two positive numbers, W:H")?;
+                options.pixel_aspect = Some(width / height);
+            }
+            "--threads" => {
+                let value = option_value(arg, args.next())?;
+                let threads = usize::from_str(value).ok()
+                    .ok_or("--threads must be a number, or 0 for one per core")?;
+                options.threads = Some(threads);
+            }
+            "--tile-size" => {
+                let value = option_value(arg, args.next())?;
+                let size = usize::from_str(value).ok()
+                    .filter(|&n| n >= 1)
+                    .ok_or("--tile-size must be a positive integer")?;
+                options.tile_size = Some(size);
+            }
+            "--checkpoint" => {
+                let value = option_value(arg, args.next())?;
+                let seconds = u64::from_str(value).ok()
+                    .filter(|&n| n >= 1)
+                    .ok_or("--checkpoint must be a positive number of seconds")?;
+                options.checkpoint_interval = Some(Duration::from_secs(seconds));
+            }
+            "--resume" => {
+                let value = option_value(arg, args.next())?;
+                options.resume = Some(value.to_string());
+            }
+            "--fps" => {
+                let value = option_value(arg, args.next())?;
+                let fps = f64::from_str(value).ok()
+                    .filter(|&f| f > 0.0 && f.is_finite())
+                    .ok_or("--fps must be a positive number")?;
+                options.fps = Some(fps);
+            }
+            "--zoom-factor" => {
+                let value = option_value(arg, args.next())?;
+                let factor = f64::from_str(value).ok()
+                    .filter(|&f| f > 0.0 && f.is_finite())
+                    .ok_or("--zoom-factor must be a positive number")?;
+                options.zoom_factor = Some(factor);
+            }
+            "--stats-json" => {
+                let value = option_value(arg, args.next())?;
+                options.stats_json = Some(value.to_string());
             }
+            "--thumbnail" => {
+                let value = option_value(arg, args.next())?;
+                let width = usize::from_str(value).ok()
+                    .filter(|&w| w >= 1)
+                    .ok_or("--thumbnail must be a positive integer")?;
+                options.thumbnail = Some(width);
+            }
+            "--data-out" => {
+                let value = option_value(arg, args.next())?;
+                if !value.ends_with(".csv") && !value.ends_with(".npy") {
+                    return Err("--data-out FILE must end in .csv or .npy".to_string());
+                }
+                options.data_out = Some(value.to_string());
+            }
+            "--check-against" => {
+                let value = option_value(arg, args.next())?;
+                options.check_against = Some(value.to_string());
+            }
+            "--lemniscate" => {
+                let value = option_value(arg, args.next())?;
+                let n = usize::from_str(value).ok()
+                    .ok_or("--lemniscate must be a non-negative integer")?;
+                options.lemniscate = Some(n);
+            }
+            "--time-heatmap" => {
+                let value = option_value(arg, args.next())?;
+                options.cost_heatmap = Some(value.to_string());
+            }
+            "--iteration-modulo" => {
+                let value = option_value(arg, args.next())?;
+                let modulo = usize::from_str(value).ok()
+                    .filter(|&m| m > 0)
+                    .ok_or("--iteration-modulo must be a positive integer")?;
+                options.shading.modulo = Some(modulo);
+            }
+            "--palette-repeats" => {
+                let value = option_value(arg, args.next())?;
+                let repeats = usize::from_str(value).ok()
+                    .filter(|&n| n > 0)
+                    .ok_or("--palette-repeats must be a positive integer")?;
+                options.shading.repeats = repeats;
+            }
+            "--power" => {
+                let value = option_value(arg, args.next())?;
+                let power = u32::from_str(value).ok()
+                    .filter(|&p| p >= 2)
+                    .ok_or("--power must be an integer of at least 2")?;
+                options.formula.power = power;
+            }
+            "--bailout" => {
+                let value = option_value(arg, args.next())?;
+                let bailout = f64::from_str(value).ok()
+                    .filter(|&r| r >= 2.0 && r.is_finite())
+                    .ok_or("--bailout must be a number of at least 2")?;
+                options.formula.bailout = bailout;
+            }
+            "--bulb-check-epsilon" => {
+                let value = option_value(arg, args.next())?;
+                let epsilon = f64::from_str(value).ok()
+                    .filter(|e| e.is_finite())
+                    .ok_or("--bulb-check-epsilon must be a number")?;
+                options.formula.bulb_epsilon = epsilon;
+            }
+            "--color-mode" => {
+                let value = option_value(arg, args.next())?;
+                options.shading.color_mode = ColorMode::from_str(value)?;
+            }
+            "--trap" => {
+                let value = option_value(arg, args.next())?;
+                options.formula.trap = Some(Trap::from_str(value)?);
+            }
+            "--light-angle" => {
+                let value = option_value(arg, args.next())?;
+                let angle = f64::from_str(value).ok()
+                    .filter(|a| a.is_finite())
+                    .ok_or("--light-angle must be a number of degrees")?;
+                options.shading.light_angle = angle;
+            }
+            "--light-height" => {
+                let value = option_value(arg, args.next())?;
+                let height = f64::from_str(value).ok()
+                    .filter(|&h| h >= 0.0 && h.is_finite())
+                    .ok_or("--light-height must be a non-negative number")?;
+                options.shading.light_height = height;
+            }
+            "--fractal" => {
+                let value = option_value(arg, args.next())?;
+                options.formula.fractal = Fractal::from_str(value)?;
+            }
+            "--gamma" => {
+                let value = option_value(arg, args.next())?;
+                let gamma = f64::from_str(value).ok()
+                    .filter(|&g| g > 0.0 && g.is_finite())
+                    .ok_or("--gamma must be a positive number")?;
+                options.gamma = Some(gamma);
+            }
+            "--downscale-aa" => {
+                let value = option_value(arg, args.next())?;
+                let factor = usize::from_str(value).ok()
+                    .filter(|&f| f >= 1)
+                    .ok_or("--downscale-aa must be a positive integer")?;
+                options.downscale = Some(factor);
+            }
+            "--cycle-mode" => {
+                let value = option_value(arg, args.next())?;
+                options.shading.cycle = CycleMode::from_str(value)?;
+            }
+            "--normalize" => {
+                let value = option_value(arg, args.next())?;
+                options.shading.normalize = Normalization::from_str(value)?;
+            }
+            _ => return Err(format!("unrecognized option: {}", arg))
         }
     }
+
+    Ok((options, positional))
+}
+
+/// Return the value following the option `name`, or an error message if there
+/// isn't one.
+fn option_value<'a>(name: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value.map(|v| v.as_str())
+        .ok_or_else(|| format!("option {} requires a value", name))
 }
 
 #[test]
-fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("",        ','), None);
-    assert_eq!(parse_pair::<i32>("10,",     ','), None);
-    assert_eq!(parse_pair::<i32>(",10",     ','), None);
-    assert_eq!(parse_pair::<i32>("10,20",   ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x",    'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+fn test_parse_args() {
+    let args: Vec<String> = ["--auto-iterations-target", "0.9", "a", "b"]
+        .iter().map(|s| s.to_string()).collect();
+    let (options, positional) = parse_args(&args).unwrap();
+    assert_eq!(options.auto_iterations_target, Some(0.9));
+    assert_eq!(positional, ["a", "b"]);
+
+    let args = vec!["--auto-iterations-target".to_string()];
+    assert!(parse_args(&args).is_err());
+    let args = vec!["--auto-iterations-target".to_string(), "1.5".to_string()];
+    assert!(parse_args(&args).is_err());
+    let args = vec!["--interior-coloring".to_string(), "last-z".to_string()];
+    assert_eq!(parse_args(&args).unwrap().0.shading.interior,
+               InteriorColoring::LastZ);
+    let args = vec!["--interior-coloring".to_string(), "plaid".to_string()];
+    assert!(parse_args(&args).is_err());
+    let args = vec!["--set-color".to_string(), "255".to_string()];
+    assert_eq!(parse_args(&args).unwrap().0.shading.set_color, 255);
+    let args = vec!["--set-color".to_string(), "256".to_string()];
+    assert!(parse_args(&args).is_err());
+    let args = vec!["--seamless".to_string(), "a".to_string()];
+    let (options, positional) = parse_args(&args).unwrap();
+    assert!(options.seamless);
+    assert_eq!(positional, ["a"]);
+    let args = vec!["--format".to_string(), "gray16".to_string()];
+    assert_eq!(parse_args(&args).unwrap().0.format, PixelFormat::Gray16);
+    let args = vec!["--format".to_string(), "ppm".to_string()];
+    let options = parse_args(&args).unwrap().0;
+    assert_eq!((options.format, options.file_format),
+               (PixelFormat::Gray8, Some(FileFormat::Ppm)));
+    let args = vec!["--format".to_string(), "rgb".to_string()];
+    assert!(parse_args(&args).is_err());
+    let args = vec!["--bogus".to_string(), "1".to_string()];
+    assert!(parse_args(&args).is_err());
 }
 
-/// Parse a pair of floating-point numbers separated by a comma as a complex
-/// number.
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair(s, ',') {
-        Some((re, im)) => Some(Complex { re, im }),
-        None => None
+/// Print a usage message for the program named `program` and exit.
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT",
+              program);
+    eprintln!("       {} --location NAME [OPTIONS] FILE PIXELS", program);
+    eprintln!("       {} --center POINT [OPTIONS] FILE PIXELS", program);
+    eprintln!("       {} --classify-points POINTS", program);
+    eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
+              program);
+    eprintln!("Points on the complex plane may be written RE,IM or RE+IMi.");
+    eprintln!("Ctrl-C while drawing a single image writes the rows finished so far.");
+    eprintln!("Options:");
+    eprintln!("  --power D");
+    eprintln!("        iterate z = z^D + c instead of z = z^2 + c, drawing the");
+    eprintln!("        multibrot set of degree D");
+    eprintln!("  --bailout R");
+    eprintln!("        count a point as escaped once its orbit leaves the circle");
+    eprintln!("        of radius R, rather than two; larger radii take a few more");
+    eprintln!("        iterations, shifting the bands of gray outwards");
+    eprintln!("  --bulb-check-epsilon E");
+    eprintln!("        require points to be E inside the main cardioid or the");
+    eprintln!("        period-2 bulb, by the tests' own measure, before drawing");
+    eprintln!("        them in the set without iterating them; positive values");
+    eprintln!("        leave points near those edges to be iterated, for close");
+    eprintln!("        views of them, and negative values loosen the tests");
+    eprintln!("        (the default is 0)");
+    eprintln!("  --fractal mandelbrot|burning-ship");
+    eprintln!("        draw the Mandelbrot set (the default), or the Burning Ship,");
+    eprintln!("        which is best viewed from -2.2,1.0 to 1.4,-1.7");
+    eprintln!("  --location NAME");
+    eprintln!("        use the view and iteration limit of a well-known spot in");
+    eprintln!("        the set, unless UPPERLEFT and LOWERRIGHT are given too;");
+    eprintln!("        --location list prints the names of the spots available");
+    eprintln!("  --center POINT");
+    eprintln!("        instead of giving UPPERLEFT and LOWERRIGHT, show a view");
+    eprintln!("        centered on POINT, three units wide, which spans the whole");
+    eprintln!("        set from left to right when POINT is -0.75");
+    eprintln!("  --zoom Z");
+    eprintln!("        with --center or --location, magnify the view Z times,");
+    eprintln!("        so that it is a Zth as wide; --zoom 1000 with --center");
+    eprintln!("        shows a view 0.003 units wide");
+    eprintln!("  --roi X0,Y0,X1,Y1");
+    eprintln!("        draw only the part of the image from column X0 and row Y0");
+    eprintln!("        up to, but not including, column X1 and row Y1, exactly");
+    eprintln!("        as it would appear in the whole image; PIXELS gives the");
+    eprintln!("        whole image's size, and FILE gets just the part");
+    eprintln!("  --rotate DEGREES");
+    eprintln!("        turn the view DEGREES counterclockwise about its center,");
+    eprintln!("        which turns the image clockwise; UPPERLEFT and LOWERRIGHT");
+    eprintln!("        give the view before it is turned");
+    eprintln!("  --aspect preserve|stretch");
+    eprintln!("        refuse to draw an image whose pixels UPPERLEFT and");
+    eprintln!("        LOWERRIGHT would make other than square (the default),");
+    eprintln!("        or draw it stretched to fit; with --pixel-aspect, refuse");
+    eprintln!("        pixels of other than that shape instead");
+    eprintln!("  --pixel-aspect W:H");
+    eprintln!("        draw for a display whose pixels are W wide by H tall, so");
+    eprintln!("        that each pixel covers W/H times as much of the real axis");
+    eprintln!("        as of the imaginary; views from --center and --location");
+    eprintln!("        take their height from this (the default is 1:1)");
+    eprintln!("  --auto-iterations-target FRACTION");
+    eprintln!("        choose the iteration limit so that about FRACTION of the");
+    eprintln!("        pixels escape, instead of using the default of 255");
+    eprintln!("  --auto-iterations");
+    eprintln!("        raise the iteration limit by 64 each time the view is half");
+    eprintln!("        as wide as the three-unit view of the whole set, so that");
+    eprintln!("        deep zooms get enough iterations; this overrides the limit");
+    eprintln!("        --location gives");
+    eprintln!("  --interior-coloring black|last-z|magnitude");
+    eprintln!("        paint points in the set black (the default), or shade them");
+    eprintln!("        by the angle or the magnitude of the last value in their");
+    eprintln!("        orbit");
+    eprintln!("  --set-color GRAY");
+    eprintln!("        with black interior coloring, paint points in the set this");
+    eprintln!("        gray level instead, from 0 (black) to 255 (white)");
+    eprintln!("  --auto-zoom FACTOR");
+    eprintln!("        find the most detailed part of the view and zoom in on it");
+    eprintln!("        by FACTOR");
+    eprintln!("  --seamless");
+    eprintln!("        blend the image's edges so that copies of it tile without");
+    eprintln!("        visible seams");
+    eprintln!("  --iteration-modulo M");
+    eprintln!("        shade escaping points by their escape count modulo M, so");
+    eprintln!("        that the grays repeat every M iterations however high the");
+    eprintln!("        iteration limit is");
+    eprintln!("  --downscale-aa K");
+    eprintln!("        antialias by rendering the image K times larger in each");
+    eprintln!("        direction and then shrinking it to size");
+    eprintln!("  --color-mode escape-time|smooth|distance|histogram|orbit-trap|lighting");
+    eprintln!("        shade escaping points by how many iterations they took to");
+    eprintln!("        escape (the default), which gives crisp bands of gray, by");
+    eprintln!("        that count smoothed so the grays blend without banding,");
+    eprintln!("        best with a --bailout of 100 or so, by their estimated");
+    eprintln!("        distance from the set, which brings out its finest");
+    eprintln!("        filaments, by how many pixels escaped faster, which spreads");
+    eprintln!("        the grays evenly, by how close their orbits came to the");
+    eprintln!("        --trap, or by lighting the set's surroundings as if");
+    eprintln!("        embossed; smoothing shows most with --iteration-modulo,");
+    eprintln!("        which gives each count several grays");
+    eprintln!("  --dither");
+    eprintln!("        with --color-mode smooth, mix neighboring grays in a fine");
+    eprintln!("        pattern where a gray falls between them, rather than");
+    eprintln!("        rounding, so that gentle gradients show no bands");
+    eprintln!("  --trap point|cross");
+    eprintln!("        with --color-mode orbit-trap, measure orbits' distance from");
+    eprintln!("        the origin (the default), or from the real and imaginary axes");
+    eprintln!("  --light-angle DEGREES");
+    eprintln!("        with --color-mode lighting, light the image from this");
+    eprintln!("        direction, counterclockwise from the right; the default is 45");
+    eprintln!("  --light-height H");
+    eprintln!("        with --color-mode lighting, the light's height relative to");
+    eprintln!("        its distance; higher lights flatten the relief (default 1.5)");
+    eprintln!("  --palette-preview STRIP");
+    eprintln!("        instead of rendering an image, write to the file STRIP a");
+    eprintln!("        strip with a column for each escape count up to the");
+    eprintln!("        iteration limit, shaded as the other options direct; no");
+    eprintln!("        FILE or other arguments are needed");
+    eprintln!("  --gamma G");
+    eprintln!("        apply gamma correction, raising each gray level to the");
+    eprintln!("        power 1/G; values above 1 brighten the midtones");
+    eprintln!("  --palette-repeats N");
+    eprintln!("        sweep through the grays N times between zero and the");
+    eprintln!("        iteration limit, rather than once, for finer bands");
+    eprintln!("        without changing the limit");
+    eprintln!("  --cycle-mode saw|triangle");
+    eprintln!("        with --iteration-modulo or --palette-repeats, either restart");
+    eprintln!("        each band of grays from light (the default), or run each");
+    eprintln!("        band back to light again so that the bands meet without");
+    eprintln!("        edges");
+    eprintln!("  --normalize linear|log|sqrt");
+    eprintln!("        with --color-mode escape-time or smooth, give every escape");
+    eprintln!("        count the same share of the grays (the default), or scale");
+    eprintln!("        them by their logarithm or square root, which spreads out");
+    eprintln!("        the low counts and packs the high ones together, as suits");
+    eprintln!("        deep zooms where most points escape late");
+    eprintln!("  --transparent-interior");
+    eprintln!("        make the points in the set transparent, for laying the image");
+    eprintln!("        over others; FILE must be a PNG");
+    eprintln!("  --format gray8|gray16");
+    eprintln!("        write 8-bit gray levels (the default), or each pixel's raw");
+    eprintln!("        escape count plus one as a 16-bit value, with zero for");
+    eprintln!("        points in the set; gray16 ignores the shading options");
+    eprintln!("  --format png|jpg|gif|pgm|ppm");
+    eprintln!("        write the image in the given format, whatever FILE's");
+    eprintln!("        extension is; if FILE is -, the image is written to");
+    eprintln!("        standard output, as a PNG unless this says otherwise");
+    eprintln!("  --jpeg-quality Q");
+    eprintln!("        when FILE ends in .jpg or .jpeg, compress it at quality Q,");
+    eprintln!("        from 1 to 100 (the default is 90)");
+    eprintln!("  --box-dimension");
+    eprintln!("        print an estimate of the box-counting dimension of the");
+    eprintln!("        boundary of the set, as drawn in the image");
+    eprintln!("  --print-counts");
+    eprintln!("        also print each pixel's escape count to standard output, as");
+    eprintln!("        a grid of numbers; useful for checking very small images");
+    eprintln!("  --thumbnail WIDTH");
+    eprintln!("        also write a copy of the image shrunk to WIDTH pixels wide,");
+    eprintln!("        keeping its proportions: FILE mandel.png gets a thumbnail");
+    eprintln!("        mandel-thumb.png");
+    eprintln!("  --time-heatmap FILE");
+    eprintln!("        also write an image to FILE showing how many iterations");
+    eprintln!("        were spent on each pixel");
+    eprintln!("  --buddhabrot");
+    eprintln!("        instead of shading points by how they escape, follow the");
+    eprintln!("        orbit of every escaping point, and make each pixel brighter");
+    eprintln!("        the more often orbits pass through it; the shading options");
+    eprintln!("        have no effect, and the view from -2.2,1.5 to 1.8,-1.5 shows");
+    eprintln!("        the whole of it");
+    eprintln!("  --lemniscate N");
+    eprintln!("        draw alternating bands between the lemniscates |z_k| = 2");
+    eprintln!("        for k up to N, leaving the region inside the last one");
+    eprintln!("        black");
+    eprintln!("  --mkdir");
+    eprintln!("        create the directory FILE is to be written in, if it");
+    eprintln!("        doesn't exist already");
+    eprintln!("  --frames N");
+    eprintln!("        render N frames of a zoom animation into the center of the");
+    eprintln!("        view, numbering each file written: FILE zoom.png becomes");
+    eprintln!("        zoom-0001.png, zoom-0002.png, and so on; if FILE ends in");
+    eprintln!("        .gif, write all the frames to it as one animated GIF");
+    eprintln!("  --zoom-factor F");
+    eprintln!("        with --frames, scale the view by F from each frame to the");
+    eprintln!("        next; values below 1 zoom in (the default is 0.9)");
+    eprintln!("  --fps N");
+    eprintln!("        with --frames and a .gif FILE, show N frames per second");
+    eprintln!("        (the default is 25)");
+    eprintln!("  --tile-size N");
+    eprintln!("        draw the image in tiles N pixels square, writing each to");
+    eprintln!("        its own file as it is finished, so that images too large");
+    eprintln!("        to hold in memory can be drawn: FILE big.png becomes");
+    eprintln!("        big-0-0.png, big-1-0.png, and so on, by column and row");
+    eprintln!("  --preview");
+    eprintln!("        first write a preview of the image to FILE, drawn at an");
+    eprintln!("        eighth of the resolution, so there's something to look at");
+    eprintln!("        in seconds; the finished image then replaces it");
+    eprintln!("  --checkpoint SECONDS");
+    eprintln!("        save the render's progress to FILE.ckpt this often, so that");
+    eprintln!("        an interrupted render can be picked up again with --resume;");
+    eprintln!("        the checkpoint is deleted once the image is written");
+    eprintln!("  --resume CHECKPOINT");
+    eprintln!("        carry on with the render saved in CHECKPOINT, rather than");
+    eprintln!("        starting again; the other arguments must be the same as");
+    eprintln!("        the interrupted render's, and --checkpoint saves to it");
+    eprintln!("  --stats-json STATS");
+    eprintln!("        write statistics about the render to the file STATS, as");
+    eprintln!("        JSON: the escaped fraction, iteration counts, and time taken");
+    eprintln!("  --data-out DATA");
+    eprintln!("        write each pixel's escape count to the file DATA, with -1");
+    eprintln!("        for points in the set: as CSV, a line per row, if DATA ends");
+    eprintln!("        in .csv, or as a NumPy array of 32-bit integers if .npy");
+    eprintln!("  --strict");
+    eprintln!("        refuse to draw views so small that f64 can't tell their");
+    eprintln!("        pixels apart, rather than drawing them with a warning");
+    eprintln!("  --threads N");
+    eprintln!("        render with N threads; by default, or if N is 0, use as");
+    eprintln!("        many as the machine has cores");
+    eprintln!("  --verbose");
+    eprintln!("        describe the view, iteration limit, and threads used, and");
+    eprintln!("        report progress while rendering, with an estimate of the");
+    eprintln!("        time remaining, and the total time taken at the end");
+    eprintln!("  --check-against REFERENCE");
+    eprintln!("        after writing the image, compare it with the image in the");
+    eprintln!("        file REFERENCE, and exit with status 2 if any pixels differ,");
+    eprintln!("        or the two aren't the same size");
+    eprintln!("  --query-pixel COLUMN,ROW");
+    eprintln!("        instead of rendering the image, print the point on the");
+    eprintln!("        complex plane its pixel at COLUMN,ROW shows, counting from");
+    eprintln!("        0,0 at the upper left, in a form UPPERLEFT, LOWERRIGHT and");
+    eprintln!("        --center accept");
+    eprintln!("  --classify-points POINTS");
+    eprintln!("        instead of rendering an image, read points from the file");
+    eprintln!("        POINTS, one RE,IM pair per line, and print CSV saying");
+    eprintln!("        whether each is in the set, and its escape time, whole and");
+    eprintln!("        smoothed, if not");
+    std::process::exit(1);
+}
+
+/// Return a line reporting that `done` of `total` pixels have been rendered
+/// after `elapsed` time, with an estimate of the time remaining, assuming the
+/// rest of the pixels go as fast as the ones so far.
+fn progress_line(done: usize, total: usize, elapsed: Duration) -> String {
+    let percent = (done * 100).checked_div(total).unwrap_or(100);
+    let eta = if done == 0 {
+        "--:--".to_string()
+    } else {
+        let remaining = elapsed.as_secs_f64() * (total - done) as f64 / done as f64;
+        let seconds = remaining.round() as u64;
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    };
+    format!("{}/{} ({}%) ETA {}", done, total, percent, eta)
+}
+
+#[test]
+fn test_progress_line() {
+    assert_eq!(progress_line(0, 100, Duration::from_secs(3)),
+               "0/100 (0%) ETA --:--");
+    assert_eq!(progress_line(25, 100, Duration::from_secs(30)),
+               "25/100 (25%) ETA 01:30");
+    assert_eq!(progress_line(100, 100, Duration::from_secs(30)),
+               "100/100 (100%) ETA 00:00");
+}
+
+/// Return the name of the checkpoint file to save the render of `filename`
+/// to: the one we resumed from, if any, or else `filename` with `.ckpt`
+/// added.
+fn checkpoint_filename(filename: &str, options: &Options) -> String {
+    match &options.resume {
+        Some(resume) => resume.clone(),
+        None => format!("{}.ckpt", filename),
+    }
+}
+
+/// Render an image as `render_parallel` would, but starting from the
+/// checkpoint named by `--resume`, if any, and saving a checkpoint to
+/// `checkpoint` as often as `--checkpoint` asks, if at all. If the
+/// checkpoint to resume from can't be read or was saved from a different
+/// render, return an error message.
+fn render_checkpointed(bounds: (usize, usize),
+                       upper_left: Complex<f64>,
+                       lower_right: Complex<f64>,
+                       formula: &Formula,
+                       options: &Options,
+                       checkpoint: &str)
+    -> Result<Vec<Escape>, String>
+{
+    // Everything that affects the escape results, so that we can tell
+    // whether a checkpoint belongs to this render.
+    let description = format!("{}x{} {},{} {},{} {:?}",
+                              bounds.0, bounds.1, upper_left.re, upper_left.im,
+                              lower_right.re, lower_right.im, formula);
+    let total = bounds.0 * bounds.1;
+
+    let done = match &options.resume {
+        None => vec![],
+        Some(resume) => {
+            let file = File::open(resume)
+                .map_err(|err| format!("error opening {}: {}", resume, err))?;
+            let (saved, done) = read_checkpoint(io::BufReader::new(file))
+                .map_err(|err| format!("error reading {}: {}", resume, err))?;
+            if saved != description || !done.len().is_multiple_of(bounds.0) || done.len() > total {
+                return Err(format!("{} was saved from a different render: {}",
+                                   resume, saved));
+            }
+            done
+        }
+    };
+
+    let start = Instant::now();
+    let mut last_save = start;
+    let escapes = render_resumable(bounds, upper_left, lower_right, formula, done,
+                                   |escapes| {
+        let interval = match options.checkpoint_interval {
+            Some(interval) if escapes.len() < total => interval,
+            _ => return,
+        };
+        if last_save.elapsed() < interval {
+            return;
+        }
+
+        // Write the new checkpoint alongside the old, and then replace it, so
+        // that being interrupted while saving doesn't lose the old one.
+        let temporary = format!("{}.tmp", checkpoint);
+        let saved = File::create(&temporary)
+            .and_then(|file| write_checkpoint(BufWriter::new(file), &description,
+                                              escapes))
+            .and_then(|()| std::fs::rename(&temporary, checkpoint));
+        match saved {
+            Ok(()) if options.verbose =>
+                eprintln!("saved checkpoint: {}",
+                          progress_line(escapes.len(), total, start.elapsed())),
+            Ok(()) => {}
+            Err(err) => eprintln!("warning: error saving checkpoint {}: {}",
+                                  checkpoint, err),
+        }
+        last_save = Instant::now();
+    });
+    Ok(escapes)
+}
+
+/// How many pixels high the strip `--palette-preview` draws is.
+const PALETTE_PREVIEW_HEIGHT: usize = 64;
+
+/// Return the pixels and dimensions of the strip `--palette-preview` draws:
+/// a column for each escape count up to `formula.limit`, shaded as `shading`
+/// directs and gamma corrected by `gamma`, if given.
+fn palette_strip(formula: &Formula, shading: &Shading, gamma: Option<f64>)
+    -> (Vec<u8>, (usize, usize))
+{
+    let mut shades = count_shades(formula, shading);
+    if let Some(gamma) = gamma {
+        apply_gamma(&mut shades, gamma);
     }
+    let bounds = (shades.len(), PALETTE_PREVIEW_HEIGHT);
+    (shades.repeat(PALETTE_PREVIEW_HEIGHT), bounds)
 }
 
 #[test]
-fn test_parse_complex() {
-    assert_eq!(parse_complex("1.25,-0.0625"),
-               Some(Complex { re: 1.25, im: -0.0625 }));
-    assert_eq!(parse_complex(",-0.0625"), None);
+fn test_palette_strip() {
+    let formula = Formula { limit: 40, ..Formula::default() };
+    let shading = Shading { modulo: Some(8), ..Shading::default() };
+    let shades = count_shades(&formula, &shading);
+    let (pixels, bounds) = palette_strip(&formula, &shading, None);
+    assert_eq!(bounds, (40, PALETTE_PREVIEW_HEIGHT));
+    for row in pixels.chunks(bounds.0) {
+        assert_eq!(row[0], shades[0]);
+        assert_eq!(row[39], shades[39]);
+        assert_eq!(row, &shades[..]);
+    }
+
+    let (brighter, _) = palette_strip(&formula, &shading, Some(2.0));
+    assert!(brighter.iter().zip(&pixels).all(|(b, p)| b >= p));
+    assert!(brighter.iter().zip(&pixels).any(|(b, p)| b > p));
 }
 
-/// Given the row and column of a pixel in the output image, return the
-/// corresponding point on the complex plane.
-///
-/// `bounds` is a pair giving the width and height of the image in pixels.
-/// `pixel` is a (column, row) pair indicating a particular pixel in that image.
-/// The `upper_left` and `lower_right` parameters are points on the complex
-/// plane designating the area our image covers.
-fn pixel_to_point(bounds: (usize, usize),
-                  pixel: (usize, usize),
-                  upper_left: Complex<f64>,
-                  lower_right: Complex<f64>)
-    -> Complex<f64>
+/// Return the point on the complex plane that `pixel` of an image `bounds`
+/// pixels in size shows, when the image's view runs from `upper_left` to
+/// `lower_right`, turned `rotate` degrees if given, as `--query-pixel` prints
+/// it. If the image has no such pixel, return an error message.
+fn query_pixel(pixel: (usize, usize),
+               bounds: (usize, usize),
+               upper_left: Complex<f64>,
+               lower_right: Complex<f64>,
+               rotate: Option<f64>)
+    -> Result<String, String>
 {
-    let (width, height) = (lower_right.re - upper_left.re,
-                           upper_left.im - lower_right.im);
-    Complex {
-        re: upper_left.re + pixel.0 as f64 * width  / bounds.0 as f64,
-        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
-        // Why subtraction here? pixel.1 increases as we go down,
-        // but the imaginary component increases as we go up.
+    if pixel.0 >= bounds.0 || pixel.1 >= bounds.1 {
+        return Err(format!("pixel {},{} is outside the {}x{} image",
+                           pixel.0, pixel.1, bounds.0, bounds.1));
     }
+    let point = rotated_pixel_to_point(bounds, pixel, upper_left, lower_right,
+                                       rotate.unwrap_or(0.0));
+    Ok(format!("{},{}", point.re, point.im))
 }
 
 #[test]
-fn test_pixel_to_point() {
-    assert_eq!(pixel_to_point((100, 200), (25, 175),
-                              Complex { re: -1.0, im:  1.0 },
-                              Complex { re:  1.0, im: -1.0 }),
-               Complex { re: -0.5, im: -0.75 });
+fn test_query_pixel() {
+    let upper_left = Complex { re: -1.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    assert_eq!(query_pixel((25, 175), (100, 200), upper_left, lower_right, None),
+               Ok("-0.5,-0.75".to_string()));
+    assert_eq!(query_pixel((0, 0), (100, 200), upper_left, lower_right, None),
+               Ok("-1,1".to_string()));
+    assert!(query_pixel((100, 0), (100, 200), upper_left, lower_right, None).is_err());
+    assert!(query_pixel((0, 200), (100, 200), upper_left, lower_right, None).is_err());
+
+    // The whole digits of the coordinates are printed, so they can be pasted
+    // back in as a corner or center.
+    let upper_left = Complex { re: -0.743643887037151, im: 0.131825904205330 };
+    let lower_right = upper_left + Complex { re: 1e-12, im: -1e-12 };
+    let point = query_pixel((0, 0), (10, 10), upper_left, lower_right, None).unwrap();
+    assert_eq!(mandelbrot::parse_complex(&point), Some(upper_left));
+
+    let args = vec!["--query-pixel".to_string(), "3,4".to_string()];
+    assert_eq!(parse_args(&args).unwrap().0.query_pixel, Some((3, 4)));
+    let args = vec!["--query-pixel".to_string(), "-3,4".to_string()];
+    assert!(parse_args(&args).is_err());
 }
 
-/// Render a rectangle of the Mandelbrot set into a buffer of pixels.
-///
-/// The `bounds` argument gives the width and height of the buffer `pixels`,
-/// which holds one grayscale pixel per byte. The `upper_left` and `lower_right`
-/// arguments specify points on the complex plane corresponding to the upper-
-/// left and lower-right corners of the pixel buffer.
-fn render(pixels: &mut [u8],
-          bounds: (usize, usize),
-          upper_left: Complex<f64>,
-          lower_right: Complex<f64>)
+/// Return a line describing the view of an image `bounds` pixels in size,
+/// from `upper_left` to `lower_right`, drawn with `formula`, for `--verbose`
+/// to print.
+fn describe_view(bounds: (usize, usize),
+                 upper_left: Complex<f64>,
+                 lower_right: Complex<f64>,
+                 formula: &Formula)
+    -> String
+{
+    format!("view {},{} to {},{}, pixels {:.3e} wide, iteration limit {}",
+            upper_left.re, upper_left.im, lower_right.re, lower_right.im,
+            (lower_right.re - upper_left.re) / bounds.0 as f64, formula.limit)
+}
+
+#[test]
+fn test_describe_view() {
+    assert_eq!(describe_view((300, 200),
+                             Complex { re: -2.0, im: 1.0 },
+                             Complex { re: 1.0, im: -1.0 },
+                             &Formula::default()),
+               "view -2,1 to 1,-1, pixels 1.000e-2 wide, iteration limit 255");
+}
+
+/// Set by the Ctrl-C handler, to ask the render under way to stop.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Set once the render is over, after which Ctrl-C exits at once.
+static RENDER_FINISHED: AtomicBool = AtomicBool::new(false);
+
+/// Render as `render_parallel` does, turning the view `rotate` degrees if
+/// given, but print a progress line to standard error every half second until
+/// the render is done. Unless the view is turned, stop early if `INTERRUPTED`
+/// is set. Return the escape results, and whether any rows were left undone,
+/// as `render_parallel_stoppable` does.
+fn render_reporting_progress(bounds: (usize, usize),
+                             upper_left: Complex<f64>,
+                             lower_right: Complex<f64>,
+                             rotate: Option<f64>,
+                             formula: &Formula)
+    -> (Vec<Escape>, bool)
+{
+    let pixels_done = AtomicUsize::new(0);
+    let total = bounds.0 * bounds.1;
+    let start = Instant::now();
+    let (finished, wait) = mpsc::channel::<()>();
+
+    thread::scope(|scope| {
+        let pixels_done = &pixels_done;
+        scope.spawn(move || {
+            // Wake up every half second, until `finished` is dropped.
+            while let Err(RecvTimeoutError::Timeout) =
+                wait.recv_timeout(Duration::from_millis(500))
+            {
+                eprintln!("{}", progress_line(pixels_done.load(Ordering::Relaxed),
+                                              total, start.elapsed()));
+            }
+        });
+
+        let result = match rotate {
+            Some(degrees) => (render_rotated(bounds, upper_left, lower_right, degrees,
+                                             formula, pixels_done), false),
+            None => render_parallel_stoppable(bounds, upper_left, lower_right,
+                                              formula, pixels_done, &INTERRUPTED)
+        };
+        drop(finished);
+        result
+    })
+}
+
+/// Return the `(keyword, text)` pairs to store in a PNG file showing the view
+/// from `upper_left` to `lower_right`, `bounds` pixels in size, drawn with
+/// `formula`, so that the image can be recreated from the file alone.
+/// `command` is the command line that drew it, which covers the shading.
+fn image_text(command: &str,
+              upper_left: Complex<f64>,
+              lower_right: Complex<f64>,
+              bounds: (usize, usize),
+              formula: &Formula)
+    -> Vec<(String, String)>
 {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    let center = (upper_left + lower_right) / 2.0;
+    let scale = (lower_right.re - upper_left.re) / bounds.0 as f64;
+    let text = vec![
+        ("Software", format!("mandelbrot {}", env!("CARGO_PKG_VERSION"))),
+        ("Command", command.to_string()),
+        ("Upper left", format!("{},{}", upper_left.re, upper_left.im)),
+        ("Lower right", format!("{},{}", lower_right.re, lower_right.im)),
+        ("Center", format!("{},{}", center.re, center.im)),
+        ("Scale", format!("{}", scale)),
+        ("Iteration limit", formula.limit.to_string()),
+        ("Precision", "f64".to_string()),
+    ];
+    text.into_iter().map(|(keyword, value)| (keyword.to_string(), value)).collect()
+}
+
+#[test]
+fn test_image_text() {
+    let text = image_text("mandelbrot a.png 100x50 -2,1 1,-0.5",
+                          Complex { re: -2.0, im: 1.0 },
+                          Complex { re: 1.0, im: -0.5 },
+                          (100, 50), &Formula::default());
+    let lookup = |keyword: &str| text.iter()
+        .find(|(k, _)| k == keyword)
+        .map(|(_, value)| value.as_str());
+    assert_eq!(lookup("Scale"), Some("0.03"));
+    assert_eq!(lookup("Center"), Some("-0.5,0.25"));
+    assert_eq!(lookup("Iteration limit"), Some("255"));
+    assert_eq!(lookup("Command"), Some("mandelbrot a.png 100x50 -2,1 1,-0.5"));
+}
+
+/// Draw the image `bounds` pixels in size, from `upper_left` to
+/// `lower_right`, as square tiles `tile_size` pixels on a side, writing each
+/// to its own file named after `filename`, so that we only ever hold one
+/// tile in memory. `formula`, `shading`, and `options` say how to draw the
+/// tiles, and `file_format` how to write them; `command` is the command line,
+/// to record in the files.
+#[allow(clippy::too_many_arguments)]
+fn render_tiles(filename: &str,
+                tile_size: usize,
+                bounds: (usize, usize),
+                upper_left: Complex<f64>,
+                lower_right: Complex<f64>,
+                formula: &Formula,
+                shading: &Shading,
+                options: &Options,
+                file_format: FileFormat,
+                command: &str)
+{
+    let factor = options.downscale.unwrap_or(1);
+    let render_bounds = (bounds.0 * factor, bounds.1 * factor);
+    let pixel_size = (lower_right.re - upper_left.re) / render_bounds.0 as f64;
+    let start = Instant::now();
+    let mut pixels_done = 0;
+
+    for (row, top) in (0..bounds.1).step_by(tile_size).enumerate() {
+        for (column, left) in (0..bounds.0).step_by(tile_size).enumerate() {
+            let tile = (left, top,
+                        tile_size.min(bounds.0 - left),
+                        tile_size.min(bounds.1 - top));
+            let render_tile_bounds = (tile.2 * factor, tile.3 * factor);
+            let escapes = render_tile(render_bounds,
+                                      (tile.0 * factor, tile.1 * factor,
+                                       render_tile_bounds.0, render_tile_bounds.1),
+                                      upper_left, lower_right, formula);
 
-    for row in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row),
-                                       upper_left, lower_right);
-            pixels[row * bounds.0 + column] =
-                match escape_time(point, 255) {
-                    None => 0,
-                    Some(count) => 255 - count as u8
-                };
+            let tile_bounds = (tile.2, tile.3);
+            let tile_upper_left = pixel_to_point(bounds, (tile.0, tile.1),
+                                                 upper_left, lower_right);
+            let tile_lower_right = pixel_to_point(bounds,
+                                                  (tile.0 + tile.2, tile.1 + tile.3),
+                                                  upper_left, lower_right);
+            let text = image_text(command, tile_upper_left, tile_lower_right,
+                                  tile_bounds, formula);
+            let name = tile_filename(filename, column, row);
+
+            if options.format == PixelFormat::Gray16 {
+                create_output(&name)
+                    .and_then(|output| encode_counts_image(output, &escapes,
+                                                           tile_bounds, &text))
+                    .expect("error writing PNG file");
+            } else {
+                let mut pixels = vec![0; escapes.len()];
+                shade(&mut pixels, &escapes, formula, pixel_size, shading);
+                if factor > 1 {
+                    pixels = downsample(&pixels, render_tile_bounds, factor);
+                }
+                if let Some(gamma) = options.gamma {
+                    apply_gamma(&mut pixels, gamma);
+                }
+                if options.transparent_interior {
+                    create_output(&name)
+                        .and_then(|output| encode_image_with_alpha(output, &pixels,
+                                                                   &interior_alpha(&escapes),
+                                                                   tile_bounds, &text))
+                        .expect("error writing PNG file");
+                } else {
+                    create_output(&name)
+                        .and_then(|output| encode_image(output, &pixels, tile_bounds,
+                                                        file_format, &text))
+                        .expect("error writing image file");
+                }
+            }
+
+            pixels_done += tile.2 * tile.3;
+            if options.verbose {
+                eprintln!("{}", progress_line(pixels_done, bounds.0 * bounds.1,
+                                              start.elapsed()));
+            }
         }
     }
 }
 
-use image::ColorType;
-use image::png::PNGEncoder;
-use std::fs::File;
+/// Draw a Buddhabrot of the view from `upper_left` to `lower_right`, `bounds`
+/// pixels in size, following orbits as `formula` says, and write it to
+/// `filename` in `file_format`. `options` gives the antialiasing, gamma, and
+/// so on, and `command` is the command line, to record in the file.
+#[allow(clippy::too_many_arguments)]
+fn render_buddhabrot(filename: &str,
+                     bounds: (usize, usize),
+                     upper_left: Complex<f64>,
+                     lower_right: Complex<f64>,
+                     formula: &Formula,
+                     options: &Options,
+                     file_format: FileFormat,
+                     command: &str)
+{
+    let factor = options.downscale.unwrap_or(1);
+    let render_bounds = (bounds.0 * factor, bounds.1 * factor);
+    let hits = buddhabrot(render_bounds, upper_left, lower_right, formula,
+                          BUDDHABROT_SAMPLES);
+    let mut pixels = shade_hits(&hits);
+    if factor > 1 {
+        pixels = downsample(&pixels, render_bounds, factor);
+    }
+    if let Some(gamma) = options.gamma {
+        apply_gamma(&mut pixels, gamma);
+    }
+    if options.seamless {
+        make_seamless(&mut pixels, bounds);
+    }
+
+    let text = image_text(command, upper_left, lower_right, bounds, formula);
+    create_output(filename)
+        .and_then(|output| encode_image(output, &pixels, bounds, file_format, &text))
+        .expect("error writing image file");
+    if let Some(width) = options.thumbnail {
+        write_thumbnail(filename, &pixels, bounds, width, upper_left, lower_right, formula,
+                        file_format, command);
+    }
+}
+
+/// Write a thumbnail `width` pixels wide of the image `pixels`, `bounds` in
+/// size, showing the view from `upper_left` to `lower_right`, to the file
+/// named after `filename`, in `file_format`. `command` and `formula` are
+/// recorded in the file, as for the image itself.
+#[allow(clippy::too_many_arguments)]
+fn write_thumbnail(filename: &str,
+                   pixels: &[u8],
+                   bounds: (usize, usize),
+                   width: usize,
+                   upper_left: Complex<f64>,
+                   lower_right: Complex<f64>,
+                   formula: &Formula,
+                   file_format: FileFormat,
+                   command: &str)
+{
+    let (small, small_bounds) = thumbnail(pixels, bounds, width);
+    let text = image_text(command, upper_left, lower_right, small_bounds, formula);
+    create_output(&thumbnail_filename(filename))
+        .and_then(|output| encode_image(output, &small, small_bounds, file_format, &text))
+        .expect("error writing thumbnail file");
+}
 
-/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to the
-/// file named `filename`.
-fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize))
-    -> Result<(), std::io::Error>
+/// Compare the image `pixels`, `bounds` in size, with the image in the file
+/// `reference`, for `--check-against`. If they don't match, return the
+/// status to exit with and a message saying why: 2 if their pixels or their
+/// sizes differ, or 1 if the reference can't be read at all.
+fn check_against(reference: &str, pixels: &[u8], bounds: (usize, usize))
+    -> Result<(), (i32, String)>
 {
-    let output = File::create(filename)?;
+    match count_mismatches(reference, pixels, bounds) {
+        Ok(0) => Ok(()),
+        Ok(mismatches) =>
+            Err((2, format!("{} of {} pixels differ from {}",
+                            mismatches, pixels.len(), reference))),
+        Err(error @ CompareError::WrongSize { .. }) =>
+            Err((2, format!("{}: {}", reference, error))),
+        Err(error @ CompareError::Unreadable(_)) =>
+            Err((1, format!("{}: {}", reference, error)))
+    }
+}
 
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(&pixels,
-                   bounds.0 as u32, bounds.1 as u32,
-                   ColorType::Gray(8))?;
+#[test]
+fn test_check_against() {
+    let pixels = vec![128; 60 * 40];
+    let reference = std::env::temp_dir()
+        .join(format!("mandelbrot-reference-{}.png", std::process::id()));
+    let reference = reference.to_str().unwrap();
+    write_image(reference, &pixels, (60, 40)).unwrap();
+    assert_eq!(check_against(reference, &pixels, (60, 40)), Ok(()));
+
+    // A reference of another size is a mismatch, not a failure to read it.
+    write_image(reference, &pixels[..30 * 20], (30, 20)).unwrap();
+    let (status, message) = check_against(reference, &pixels, (60, 40)).unwrap_err();
+    assert_eq!(status, 2);
+    assert!(message.contains("30x20") && message.contains("60x40"), "{}", message);
 
+    std::fs::remove_file(reference).unwrap();
+    assert_eq!(check_against(reference, &pixels, (60, 40)).unwrap_err().0, 1);
+}
+
+/// Check that we can draw an image `bounds` pixels in size, rendered at
+/// `factor` times that size in each direction, returning a message
+/// explaining the problem if not. If `tiled` is false, we need room for an
+/// `Escape` for every pixel of the render at once, so the image must be
+/// small enough that the size of that buffer doesn't overflow.
+fn check_bounds(bounds: (usize, usize), factor: usize, tiled: bool) -> Result<(), String> {
+    if bounds.0 == 0 || bounds.1 == 0 {
+        return Err("the image must be at least one pixel wide and high".to_string());
+    }
+    let pixels = bounds.0.checked_mul(factor)
+        .and_then(|width| Some((width, bounds.1.checked_mul(factor)?)))
+        .and_then(|(width, height)| width.checked_mul(height))
+        .ok_or(format!("{}x{} is too many pixels to count", bounds.0, bounds.1))?;
+    let bytes = pixels.checked_mul(std::mem::size_of::<Escape>())
+        .filter(|&bytes| bytes <= isize::MAX as usize);
+    if !tiled && bytes.is_none() {
+        return Err(format!("{}x{} is too large to render all at once; \
+                            try --tile-size", bounds.0, bounds.1));
+    }
     Ok(())
 }
 
-use std::env;
+#[test]
+fn test_check_bounds() {
+    assert_eq!(check_bounds((640, 480), 1, false), Ok(()));
+    assert!(check_bounds((0, 480), 1, false).is_err());
+    assert!(check_bounds((640, 0), 1, true).is_err());
+
+    let huge = (usize::MAX / 2, 3);
+    assert!(check_bounds(huge, 1, false).is_err());
+    assert!(check_bounds(huge, 1, true).is_err());
+    assert!(check_bounds((1 << 20, 1 << 20), 1 << 20, true).is_err());
+
+    // Too large to hold at once, but fine a tile at a time.
+    let large = (1 << 32, 1 << 28);
+    assert!(check_bounds(large, 1, false).unwrap_err().contains("--tile-size"));
+    assert_eq!(check_bounds(large, 1, true), Ok(()));
+}
+
+/// Return the part of an image `bounds` in size that `--roi` asks for, from
+/// column `x0` and row `y0` up to, but not including, column `x1` and row
+/// `y1`, as the left column, top row, width, and height of a tile. A region
+/// running off the image is cut off at its edges; one with nothing left is
+/// an error.
+fn check_region(roi: (usize, usize, usize, usize), bounds: (usize, usize))
+    -> Result<(usize, usize, usize, usize), String>
+{
+    let (x0, y0, x1, y1) = roi;
+    let (x1, y1) = (x1.min(bounds.0), y1.min(bounds.1));
+    if x0 >= x1 || y0 >= y1 {
+        return Err(format!("--roi {},{},{},{} includes no pixels of the {}x{} image",
+                           roi.0, roi.1, roi.2, roi.3, bounds.0, bounds.1));
+    }
+    Ok((x0, y0, x1 - x0, y1 - y0))
+}
+
+#[test]
+fn test_check_region() {
+    assert_eq!(check_region((10, 20, 30, 60), (100, 100)), Ok((10, 20, 20, 40)));
+    assert_eq!(check_region((90, 0, 200, 100), (100, 50)), Ok((90, 0, 10, 50)));
+    assert!(check_region((30, 20, 10, 60), (100, 100)).is_err());
+    assert!(check_region((10, 20, 10, 60), (100, 100)).is_err());
+    assert!(check_region((100, 0, 120, 10), (100, 100)).is_err());
+
+    let args = vec!["--roi".to_string(), "1,2,3,4".to_string()];
+    assert_eq!(parse_args(&args).unwrap().0.roi, Some((1, 2, 3, 4)));
+    for bad in &["1,2,3", "1,2,3,4,5", "1,2,-3,4", "1,2,3,x"] {
+        let args = vec!["--roi".to_string(), bad.to_string()];
+        assert!(parse_args(&args).is_err());
+    }
+}
+
+#[test]
+fn test_region_matches_full_render() {
+    let bounds = (80, 60);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+    let formula = Formula::default();
+    let shading = Shading::default();
+    let pixel_size = 0.2 / 80.0;
+
+    let full = render_parallel(bounds, upper_left, lower_right, &formula);
+    let mut full_pixels = vec![0; full.len()];
+    shade(&mut full_pixels, &full, &formula, pixel_size, &shading);
+
+    let (left, top, width, height) = check_region((23, 17, 61, 200), bounds).unwrap();
+    let region = render_tile(bounds, (left, top, width, height),
+                             upper_left, lower_right, &formula);
+    let mut region_pixels = vec![0; region.len()];
+    shade(&mut region_pixels, &region, &formula, pixel_size, &shading);
+
+    assert_eq!(region_pixels[0], full_pixels[top * bounds.0 + left]);
+    for row in 0..height {
+        assert_eq!(region_pixels[row * width..(row + 1) * width],
+                   full_pixels[(top + row) * bounds.0 + left..][..width]);
+    }
+}
+
+/// An image's dimensions in pixels, and the upper left and lower right
+/// corners of the view it shows.
+type View = ((usize, usize), Complex<f64>, Complex<f64>);
+
+/// Parse the image dimensions and the corners of the view from the
+/// positional arguments `args` (FILE, PIXELS, and UPPERLEFT and LOWERRIGHT
+/// unless `options` supply the view), and check that they make sense. Return
+/// the dimensions and corners, or a message saying what's wrong.
+fn parse_view(args: &[String], options: &Options) -> Result<View, String> {
+    let bounds = parse_pair(&args[1], 'x')
+        .ok_or(format!("can't parse image dimensions: {}", args[1]))?;
+    check_bounds(bounds, options.downscale.unwrap_or(1),
+                 options.tile_size.is_some() || options.roi.is_some())?;
+
+    let zoom = options.zoom.unwrap_or(1.0);
+    let pixel_aspect = options.pixel_aspect.unwrap_or(1.0);
+    let (upper_left, lower_right) = match (options.center, options.location) {
+        (Some(center), _) =>
+            view_corners(center, DEFAULT_VIEW_WIDTH / zoom, bounds, pixel_aspect),
+        (None, Some(location)) if args.len() == 2 =>
+            location.corners(bounds, zoom, pixel_aspect),
+        _ => (parse_complex(&args[2])
+                  .ok_or(format!("can't parse upper left corner point: {}", args[2]))?,
+              parse_complex(&args[3])
+                  .ok_or(format!("can't parse lower right corner point: {}", args[3]))?)
+    };
+    // If `f64` can't resolve the pixels, their shape is hard to judge; main
+    // says so instead.
+    if options.aspect == Aspect::Preserve
+        && check_precision(bounds, upper_left, lower_right).is_ok()
+    {
+        check_aspect(bounds, upper_left, lower_right, pixel_aspect)?;
+    }
+    Ok((bounds, upper_left, lower_right))
+}
+
+#[test]
+fn test_parse_view() {
+    let args: Vec<String> = ["out.png", "300x200", "-2,1", "1,-1"].iter()
+        .map(|s| s.to_string())
+        .collect();
+    let options = Options::default();
+    assert_eq!(parse_view(&args, &options),
+               Ok(((300, 200), Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 })));
+
+    let mut bad = args.clone();
+    bad[1] = "300by200".to_string();
+    assert!(parse_view(&bad, &options).unwrap_err().contains("image dimensions"));
+    bad[1] = "0x200".to_string();
+    assert!(parse_view(&bad, &options).unwrap_err().contains("at least one pixel"));
+
+    let mut bad = args.clone();
+    bad[2] = "-2;1".to_string();
+    assert!(parse_view(&bad, &options).unwrap_err().contains("upper left"));
+    let mut bad = args.clone();
+    bad[3] = "one,-1".to_string();
+    assert!(parse_view(&bad, &options).unwrap_err().contains("lower right"));
+
+    // Stretched views are an error unless asked for.
+    let mut stretched = args.clone();
+    stretched[1] = "300x300".to_string();
+    assert!(parse_view(&stretched, &options).is_err());
+    let options = Options { aspect: Aspect::Stretch, ..Options::default() };
+    assert!(parse_view(&stretched, &options).is_ok());
+}
+
+#[test]
+fn test_center_corners() {
+    // The corners the command line gives for a center and zoom are those
+    // the library derives from the same center and width.
+    let view = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let (options, positional) = parse_args(&args).unwrap();
+        parse_view(&positional, &options).unwrap()
+    };
+    let center = Complex { re: -0.75, im: 0.1 };
+    let (bounds, upper_left, lower_right) =
+        view(&["--center", "-0.75,0.1", "--zoom", "4", "out.png", "300x200"]);
+    assert_eq!(bounds, (300, 200));
+    assert_eq!((upper_left, lower_right),
+               view_corners(center, DEFAULT_VIEW_WIDTH / 4.0, bounds, 1.0));
+    assert!((upper_left - Complex { re: -1.125, im: 0.35 }).norm() < 1e-12);
+    assert!((lower_right - Complex { re: -0.375, im: -0.15 }).norm() < 1e-12);
+    assert_eq!(check_aspect(bounds, upper_left, lower_right, 1.0), Ok(()));
+
+    // The same goes for pixels of another shape, and for named locations.
+    let (bounds, upper_left, lower_right) =
+        view(&["--center", "-0.75,0.1", "--pixel-aspect", "2:1", "out.png", "300x200"]);
+    assert_eq!((upper_left, lower_right),
+               view_corners(center, DEFAULT_VIEW_WIDTH, bounds, 2.0));
+    let (bounds, upper_left, lower_right) =
+        view(&["--location", "seahorse-valley", "--zoom", "8", "out.png", "300x200"]);
+    assert_eq!((upper_left, lower_right),
+               find_location("seahorse-valley").unwrap().corners(bounds, 8.0, 1.0));
+}
+
+/// Return the number of threads to render with, given the number requested
+/// on the command line, if any. Asking for none, or for zero, means one per
+/// core, or just one if we can't tell how many cores there are.
+/// Check that `options` doesn't combine options that contradict each
+/// other, returning a message naming them if it does.
+fn check_combinations(options: &Options) -> Result<(), String> {
+    if options.lemniscate.is_some() && options.auto_iterations_target.is_some() {
+        return Err("--lemniscate and --auto-iterations-target \
+                    cannot be used together".to_string());
+    }
+    if options.auto_iterations
+        && (options.lemniscate.is_some() || options.auto_iterations_target.is_some())
+    {
+        return Err("--auto-iterations can't be used with --lemniscate \
+                    or --auto-iterations-target".to_string());
+    }
+
+    // Each of these decides for itself how the grays repeat.
+    if options.shading.repeats > 1
+        && (options.shading.modulo.is_some() || options.lemniscate.is_some())
+    {
+        return Err("--palette-repeats can't be used with --iteration-modulo \
+                    or --lemniscate".to_string());
+    }
+    if options.lemniscate.is_some() && options.shading.modulo.is_some() {
+        return Err("--iteration-modulo can't be used with --lemniscate, \
+                    which alternates the grays itself".to_string());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_combinations() {
+    let check = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        check_combinations(&parse_args(&args).unwrap().0)
+    };
+    assert_eq!(check(&["--lemniscate", "5"]), Ok(()));
+    assert_eq!(check(&["--iteration-modulo", "3"]), Ok(()));
+    assert!(check(&["--lemniscate", "5", "--iteration-modulo", "3"])
+            .unwrap_err().contains("--iteration-modulo"));
+    assert!(check(&["--lemniscate", "5", "--palette-repeats", "2"]).is_err());
+    assert!(check(&["--lemniscate", "5", "--auto-iterations-target", "0.5"]).is_err());
+    assert!(check(&["--auto-iterations", "--lemniscate", "5"]).is_err());
+}
+
+fn thread_count(requested: Option<usize>) -> usize {
+    match requested {
+        Some(n) if n > 0 => n,
+        _ => thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    }
+}
+
+#[test]
+fn test_thread_count() {
+    let cores = thread::available_parallelism().unwrap().get();
+    assert_eq!(thread_count(None), cores);
+    assert_eq!(thread_count(Some(0)), cores);
+    assert_eq!(thread_count(Some(3)), 3);
+}
+
+/// Create the file named `filename` for writing, or use standard output if
+/// `filename` is `-`. Either way, buffer the output.
+fn create_output(filename: &str) -> io::Result<Box<dyn Write>> {
+    if filename == "-" {
+        Ok(Box::new(BufWriter::new(io::stdout().lock())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(filename)?)))
+    }
+}
 
 fn main() {
+    let program_start = Instant::now();
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 5 {
-        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT",
-                  args[0]);
-        eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
-                  args[0]);
+    let program = &args[0];
+    let command = args.join(" ");
+
+    let (options, args) = match parse_args(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}: {}", program, message);
+            usage(program);
+        }
+    };
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(thread_count(options.threads))
+        .build_global()
+        .expect("error starting rendering threads");
+
+    if let Some(points) = &options.classify_points {
+        if !args.is_empty() {
+            usage(program);
+        }
+        let stdout = io::stdout();
+        let result = File::open(points)
+            .and_then(|file| classify_points(io::BufReader::new(file),
+                                             &mut stdout.lock(), &options.formula));
+        if let Err(error) = result {
+            eprintln!("{}: {}: {}", program, points, error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(strip_file) = &options.palette_preview {
+        if !args.is_empty() {
+            usage(program);
+        }
+        let color_mode = options.shading.color_mode;
+        if color_mode != ColorMode::EscapeTime && color_mode != ColorMode::Smooth {
+            eprintln!("{}: --palette-preview shows how escape counts are shaded, \
+                       which only --color-mode escape-time and smooth use", program);
+            std::process::exit(1);
+        }
+        let mut formula = options.formula;
+        if let Some(location) = options.location {
+            formula.limit = location.limit;
+        }
+        let (pixels, bounds) = palette_strip(&formula, &options.shading, options.gamma);
+        write_image(strip_file, &pixels, bounds).expect("error writing palette preview");
+        return;
+    }
+
+    if options.list_locations {
+        for location in LOCATIONS {
+            println!("{}", location.name);
+        }
+        return;
+    }
+
+    // A location supplies the corners, but explicit ones take precedence.
+    // A center supplies them too, but can't be combined with either.
+    let view_given = options.location.is_some() || options.center.is_some();
+    if args.len() != 4 && !(args.len() == 2 && view_given) {
+        usage(program);
+    }
+    if options.center.is_some() && (options.location.is_some() || args.len() == 4) {
+        eprintln!("{}: --center can't be used with --location, or with \
+                   UPPERLEFT and LOWERRIGHT", program);
+        std::process::exit(1);
+    }
+    if options.zoom.is_some() && args.len() == 4 {
+        eprintln!("{}: --zoom needs --center or --location, and can't be used \
+                   with UPPERLEFT and LOWERRIGHT", program);
         std::process::exit(1);
     }
 
-    let bounds = parse_pair(&args[2], 'x')
-        .expect("error parsing image dimensions");
-    let upper_left = parse_complex(&args[3])
-        .expect("error parsing upper left corner point");
-    let lower_right = parse_complex(&args[4])
-        .expect("error parsing lower right corner point");
+    // A FILE of `-` means standard output, which has no extension to go by.
+    let to_stdout = args[0] == "-";
+    let file_format = match options.file_format {
+        Some(format) => Ok(format),
+        None if to_stdout => Ok(FileFormat::Png),
+        None => FileFormat::from_filename(&args[0])
+    };
+    let file_format = match file_format {
+        Ok(FileFormat::Jpeg(_)) =>
+            FileFormat::Jpeg(options.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY)),
+        Ok(format) => format,
+        Err(message) => {
+            eprintln!("{}: {}", program, message);
+            std::process::exit(1);
+        }
+    };
 
-    let mut pixels = vec![0; bounds.0 * bounds.1];
+    // Raw escape counts can't be downsampled, and only PNG holds 16 bits.
+    if options.format == PixelFormat::Gray16
+        && (options.downscale.unwrap_or(1) > 1 || file_format != FileFormat::Png)
+    {
+        eprintln!("{}: --format gray16 needs a .png FILE, and can't be used \
+                   with --downscale-aa", program);
+        std::process::exit(1);
+    }
 
-    // Scope of slicing up `pixels` into horizontal bands.
+    // The alpha channel comes straight from the escape results, so it can't
+    // be downsampled either, and only PNG has one.
+    if options.transparent_interior
+        && (options.downscale.unwrap_or(1) > 1 || file_format != FileFormat::Png
+            || options.format == PixelFormat::Gray16)
     {
-        let bands: Vec<(usize, &mut [u8])> = pixels
-            .chunks_mut(bounds.0)
-            .enumerate()
-            .collect();
-
-        bands.into_par_iter()
-            .for_each(|(i, band)| {
-                let top = i;
-                let band_bounds = (bounds.0, 1);
-                let band_upper_left = pixel_to_point(bounds, (0, top),
-                                                     upper_left, lower_right);
-                let band_lower_right = pixel_to_point(bounds, (bounds.0, top + 1),
-                                                      upper_left, lower_right);
-                render(band, band_bounds, band_upper_left, band_lower_right);
-            });
-    }
-
-    write_image(&args[1], &pixels, bounds)
-        .expect("error writing PNG file");
+        eprintln!("{}: --transparent-interior needs a .png FILE, and can't be \
+                   used with --downscale-aa or --format gray16", program);
+        std::process::exit(1);
+    }
+
+    // With `--frames`, we write either a single animated GIF, or a numbered
+    // file for each frame.
+    let animated_gif = options.frames.is_some() && file_format == FileFormat::Gif;
+    let first_output = match (options.frames, options.tile_size) {
+        (Some(_), _) if !animated_gif => frame_filename(&args[0], 1),
+        (_, Some(_)) => tile_filename(&args[0], 0, 0),
+        _ => args[0].clone()
+    };
+
+    // Tiles are shaded and written one at a time, so anything that needs
+    // the whole image at once won't work.
+    if options.tile_size.is_some()
+        && (options.frames.is_some() || options.seamless || to_stdout
+            || options.shading.color_mode == ColorMode::Histogram
+            || options.box_dimension || options.print_counts
+            || options.cost_heatmap.is_some() || options.check_against.is_some()
+            || options.stats_json.is_some() || options.data_out.is_some())
+    {
+        eprintln!("{}: --tile-size can't be used with --frames, --seamless, \
+                   --color-mode histogram, --box-dimension, --print-counts, \
+                   --time-heatmap, --check-against, --stats-json, --data-out, \
+                   or a FILE of -", program);
+        std::process::exit(1);
+    }
+    if options.dither
+        && (options.shading.color_mode != ColorMode::Smooth || options.tile_size.is_some())
+    {
+        eprintln!("{}: --dither needs --color-mode smooth, and can't be used \
+                   with --tile-size", program);
+        std::process::exit(1);
+    }
+    if options.thumbnail.is_some()
+        && (options.tile_size.is_some() || animated_gif || to_stdout)
+    {
+        eprintln!("{}: --thumbnail can't be used with --tile-size, an animated \
+                   GIF, or a FILE of -", program);
+        std::process::exit(1);
+    }
+    if options.preview && (options.frames.is_some() || options.tile_size.is_some() || to_stdout) {
+        eprintln!("{}: --preview can't be used with --frames, --tile-size, or a \
+                   FILE of -", program);
+        std::process::exit(1);
+    }
+    let checkpointing = options.checkpoint_interval.is_some() || options.resume.is_some();
+
+    // Only the whole-image renderers know how to turn the view, and the
+    // detail --auto-zoom finds is in the view as it is.
+    if options.rotate.is_some()
+        && (options.tile_size.is_some() || options.preview || checkpointing
+            || options.buddhabrot || options.auto_zoom.is_some())
+    {
+        eprintln!("{}: --rotate can't be used with --tile-size, --preview, \
+                   --checkpoint, --resume, --buddhabrot, or --auto-zoom", program);
+        std::process::exit(1);
+    }
+
+    // A region is rendered as a tile of the full image, so that it lines up
+    // with it exactly.
+    if options.roi.is_some()
+        && (options.frames.is_some() || options.tile_size.is_some() || options.preview
+            || checkpointing || options.buddhabrot || options.rotate.is_some())
+    {
+        eprintln!("{}: --roi can't be used with --frames, --tile-size, --preview, \
+                   --checkpoint, --resume, --buddhabrot, or --rotate", program);
+        std::process::exit(1);
+    }
+
+    // A Buddhabrot counts orbits' visits to pixels, so there are no escape
+    // counts to save or print, and it takes the whole image to normalize.
+    if options.buddhabrot
+        && (options.frames.is_some() || options.tile_size.is_some() || options.preview
+            || checkpointing || options.format == PixelFormat::Gray16
+            || options.transparent_interior || options.box_dimension
+            || options.print_counts || options.cost_heatmap.is_some()
+            || options.check_against.is_some() || options.stats_json.is_some()
+            || options.data_out.is_some())
+    {
+        eprintln!("{}: --buddhabrot can't be used with --frames, --tile-size, \
+                   --preview, --checkpoint, --resume, --format gray16, \
+                   --transparent-interior, --box-dimension, --print-counts, \
+                   --time-heatmap, --check-against, --stats-json, or --data-out",
+                  program);
+        std::process::exit(1);
+    }
+    if checkpointing && (options.frames.is_some() || options.tile_size.is_some() || to_stdout) {
+        eprintln!("{}: --checkpoint and --resume can't be used with --frames, \
+                   --tile-size, or a FILE of -", program);
+        std::process::exit(1);
+    }
+    if to_stdout && (options.print_counts || options.box_dimension) {
+        eprintln!("{}: can't print text to standard output while writing \
+                   the image there", program);
+        std::process::exit(1);
+    }
+
+    let (bounds, mut upper_left, mut lower_right) = match parse_view(&args, &options) {
+        Ok(view) => view,
+        Err(message) => {
+            eprintln!("{}: {}", program, message);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(message) = check_combinations(&options) {
+        eprintln!("{}: {}", program, message);
+        std::process::exit(1);
+    }
+
+    let region = options.roi.map(|roi| check_region(roi, bounds).unwrap_or_else(|message| {
+        eprintln!("{}: {}", program, message);
+        std::process::exit(1);
+    }));
+
+    // Querying a pixel writes nothing, so there's nothing to check.
+    if !to_stdout && options.query_pixel.is_none() {
+        if let Err(message) = check_output_path(&first_output, options.mkdir) {
+            eprintln!("{}: {}", program, message);
+            std::process::exit(1);
+        }
+    }
+
+    let mut shading = options.shading;
+    let mut formula = options.formula;
+    if let Some(location) = options.location {
+        formula.limit = location.limit;
+    }
+    formula.estimate_distance = shading.color_mode == ColorMode::Distance;
+    formula.estimate_normal = shading.color_mode == ColorMode::Lighting;
+    formula.trap = if shading.color_mode == ColorMode::OrbitTrap {
+        Some(formula.trap.unwrap_or(Trap::Point))
+    } else {
+        None
+    };
+    // Interior shading uses the last `z` of each orbit, which skipping
+    // iterations would change.
+    formula.skip_interior = shading.interior == InteriorColoring::Black;
+    formula.limit = match (options.lemniscate, options.auto_iterations_target) {
+        (Some(n), _) => {
+            // A point escaping on iteration k lies between the lemniscates
+            // for k - 1 and k, so shading alternate counts alternately
+            // draws the bands.
+            shading.modulo = Some(2);
+            n + 1
+        }
+        (None, Some(target)) => {
+            let limit = auto_iteration_limit(target, bounds,
+                                             upper_left, lower_right, &formula);
+            eprintln!("using an iteration limit of {}", limit);
+            limit
+        }
+        (None, None) => formula.limit
+    };
+
+    if let Some(factor) = options.auto_zoom {
+        let (zoomed_upper_left, zoomed_lower_right) =
+            auto_zoom(factor, bounds, upper_left, lower_right, &formula);
+        upper_left = zoomed_upper_left;
+        lower_right = zoomed_lower_right;
+        eprintln!("zooming in on {},{} {},{}",
+                  upper_left.re, upper_left.im, lower_right.re, lower_right.im);
+    }
+
+    if options.auto_iterations {
+        formula.limit = zoom_iteration_limit(upper_left, lower_right);
+        eprintln!("using an iteration limit of {}", formula.limit);
+    }
+
+    // With --frames, the last frame is the deepest.
+    let deepest_scale = options.zoom_factor.unwrap_or(DEFAULT_ZOOM_FACTOR)
+        .powi(options.frames.unwrap_or(1) as i32 - 1);
+    let center = (upper_left + lower_right) / 2.0;
+    let half = (lower_right - upper_left) / 2.0 * deepest_scale.min(1.0);
+    if let Err(message) = check_precision(bounds, center - half, center + half) {
+        if options.strict {
+            eprintln!("{}: {}", program, message);
+            std::process::exit(1);
+        }
+        eprintln!("warning: {}", message);
+    }
+
+    if let Some(pixel) = options.query_pixel {
+        match query_pixel(pixel, bounds, upper_left, lower_right, options.rotate) {
+            Ok(point) => println!("{}", point),
+            Err(message) => {
+                eprintln!("{}: {}", program, message);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if options.verbose {
+        eprintln!("threads: {}, precision: f64", rayon::current_num_threads());
+        if options.frames.is_none() {
+            eprintln!("{}", describe_view(bounds, upper_left, lower_right, &formula));
+        }
+    }
+
+    // With --roi, the image is just the region, showing its part of the
+    // view, but rendered as part of the whole.
+    let full_view = (bounds, upper_left, lower_right);
+    let (bounds, upper_left, lower_right) = match region {
+        Some((left, top, width, height)) =>
+            ((width, height),
+             pixel_to_point(bounds, (left, top), upper_left, lower_right),
+             pixel_to_point(bounds, (left + width, top + height), upper_left, lower_right)),
+        None => full_view
+    };
+
+    if options.buddhabrot {
+        render_buddhabrot(&args[0], bounds, upper_left, lower_right, &formula, &options,
+                          file_format, &command);
+        if options.verbose {
+            eprintln!("total time: {:.2}s", program_start.elapsed().as_secs_f64());
+        }
+        return;
+    }
+
+    if let Some(tile_size) = options.tile_size {
+        render_tiles(&args[0], tile_size, bounds, upper_left, lower_right,
+                     &formula, &shading, &options, file_format, &command);
+        if options.verbose {
+            eprintln!("total time: {:.2}s", program_start.elapsed().as_secs_f64());
+        }
+        return;
+    }
+
+    let frames = options.frames.unwrap_or(1);
+    let mut animation = Vec::new();
+    for frame in 0..frames {
+        // Each frame after the first zooms in (or out) on the center of the
+        // view, and needs its own iteration limit if we're choosing those
+        // automatically.
+        let (upper_left, lower_right) = if frame > 0 {
+            let center = (upper_left + lower_right) / 2.0;
+            let scale = options.zoom_factor.unwrap_or(DEFAULT_ZOOM_FACTOR)
+                .powi(frame as i32);
+            let half = (lower_right - upper_left) / 2.0 * scale;
+            (center - half, center + half)
+        } else {
+            (upper_left, lower_right)
+        };
+        let mut formula = formula;
+        if frame > 0 {
+            if let Some(target) = options.auto_iterations_target {
+                formula.limit = auto_iteration_limit(target, bounds, upper_left,
+                                                     lower_right, &formula);
+                eprintln!("frame {}: using an iteration limit of {}",
+                          frame + 1, formula.limit);
+            } else if options.auto_iterations {
+                formula.limit = zoom_iteration_limit(upper_left, lower_right);
+                eprintln!("frame {}: using an iteration limit of {}",
+                          frame + 1, formula.limit);
+            }
+        }
+
+        if options.verbose && options.frames.is_some() {
+            eprintln!("frame {}: {}", frame + 1,
+                      describe_view(bounds, upper_left, lower_right, &formula));
+        }
+
+        // When rendering frames, number every file we write.
+        let name = |filename: &str| match options.frames {
+            Some(_) if filename != "-" => frame_filename(filename, frame + 1),
+            _ => filename.to_string()
+        };
+
+        let factor = options.downscale.unwrap_or(1);
+        let render_bounds = (bounds.0 * factor, bounds.1 * factor);
+        let pixel_size = (lower_right.re - upper_left.re) / render_bounds.0 as f64;
+        let to_pixels = |escapes: &[Escape]| {
+            let mut pixels = vec![0; escapes.len()];
+            shade(&mut pixels, escapes, &formula, pixel_size, &shading);
+            if options.dither {
+                dither_smooth(&mut pixels, escapes, render_bounds, &formula, &shading);
+            }
+            if factor > 1 {
+                pixels = downsample(&pixels, render_bounds, factor);
+            }
+            if let Some(gamma) = options.gamma {
+                apply_gamma(&mut pixels, gamma);
+            }
+            if options.seamless {
+                make_seamless(&mut pixels, bounds);
+            }
+            pixels
+        };
+        let text = image_text(&command, upper_left, lower_right, bounds, &formula);
+        let write = |escapes: &[Escape], pixels: &[u8]| {
+            if options.format == PixelFormat::Gray16 {
+                create_output(&name(&args[0]))
+                    .and_then(|output| encode_counts_image(output, escapes, bounds, &text))
+                    .expect("error writing PNG file");
+            } else if options.transparent_interior {
+                create_output(&name(&args[0]))
+                    .and_then(|output| encode_image_with_alpha(output, pixels,
+                                                               &interior_alpha(escapes),
+                                                               bounds, &text))
+                    .expect("error writing PNG file");
+            } else {
+                create_output(&name(&args[0]))
+                    .and_then(|output| encode_image(output, pixels, bounds, file_format,
+                                                    &text))
+                    .expect("error writing image file");
+            }
+        };
+
+        if options.preview {
+            let start = Instant::now();
+            let preview = render_preview(render_bounds, PREVIEW_STEP, upper_left,
+                                         lower_right, &formula);
+            write(&preview, &to_pixels(&preview));
+            if options.verbose {
+                eprintln!("wrote preview in {:.2}s", start.elapsed().as_secs_f64());
+            }
+        }
+
+        // While a single image's render is under way, Ctrl-C stops it, and
+        // we write what's done; a second Ctrl-C, or one once the render is
+        // over, kills the program as usual. Elsewhere, so does the first.
+        if options.frames.is_none() && region.is_none() && !checkpointing
+            && options.rotate.is_none()
+        {
+            ctrlc::set_handler(|| {
+                if RENDER_FINISHED.load(Ordering::Relaxed)
+                    || INTERRUPTED.swap(true, Ordering::Relaxed)
+                {
+                    std::process::exit(130);
+                }
+            })
+            .expect("error setting Ctrl-C handler");
+        }
+
+        let start = Instant::now();
+        let (escapes, stopped) = if let Some((left, top, width, height)) = region {
+            let (full_bounds, full_upper_left, full_lower_right) = full_view;
+            (render_tile((full_bounds.0 * factor, full_bounds.1 * factor),
+                         (left * factor, top * factor, width * factor, height * factor),
+                         full_upper_left, full_lower_right, &formula), false)
+        } else if checkpointing {
+            (render_checkpointed(render_bounds, upper_left, lower_right, &formula,
+                                 &options, &checkpoint_filename(&args[0], &options))
+                 .unwrap_or_else(|message| {
+                     eprintln!("{}: {}", program, message);
+                     std::process::exit(1);
+                 }), false)
+        } else if options.verbose {
+            render_reporting_progress(render_bounds, upper_left, lower_right,
+                                      options.rotate, &formula)
+        } else if let Some(degrees) = options.rotate {
+            (render_rotated(render_bounds, upper_left, lower_right, degrees, &formula,
+                            &AtomicUsize::new(0)), false)
+        } else {
+            render_parallel_stoppable(render_bounds, upper_left, lower_right, &formula,
+                                      &AtomicUsize::new(0), &INTERRUPTED)
+        };
+        RENDER_FINISHED.store(true, Ordering::Relaxed);
+        let pixels = to_pixels(&escapes);
+        let elapsed = start.elapsed();
+
+        if let Some(stats_file) = &options.stats_json {
+            std::fs::write(name(stats_file),
+                           stats_json(&escapes, render_bounds, formula.limit, elapsed))
+                .expect("error writing statistics file");
+        }
+
+        if let Some(data_file) = &options.data_out {
+            let file = File::create(name(data_file)).expect("error creating data file");
+            let mut output = BufWriter::new(file);
+            if data_file.ends_with(".npy") {
+                write_counts_npy(&mut output, &escapes, render_bounds)
+            } else {
+                write_counts_csv(&mut output, &escapes, render_bounds)
+            }
+            .and_then(|()| output.flush())
+            .expect("error writing data file");
+        }
+
+        if options.box_dimension {
+            match box_dimension(&escapes, render_bounds) {
+                Some(dimension) =>
+                    println!("box-counting dimension of the boundary: {:.3}", dimension),
+                None => println!("the image is too small or shows no boundary"),
+            }
+        }
+
+        if options.print_counts {
+            let stdout = io::stdout();
+            print_counts(&mut stdout.lock(), &escapes, render_bounds)
+                .expect("error printing escape counts");
+        }
+
+        if let Some(heatmap_file) = &options.cost_heatmap {
+            write_image(&name(heatmap_file), &cost_heatmap(&escapes, formula.limit),
+                        render_bounds)
+                .expect("error writing heat map image file");
+        }
+
+        if animated_gif {
+            animation.push(pixels.clone());
+        } else {
+            write(&escapes, &pixels);
+        }
+        if stopped {
+            eprintln!("{}: interrupted; {} has only the rows finished, with the rest \
+                       drawn as the set's interior", program, args[0]);
+            std::process::exit(130);
+        }
+        if let Some(width) = options.thumbnail {
+            write_thumbnail(&name(&args[0]), &pixels, bounds, width, upper_left, lower_right,
+                            &formula, file_format, &command);
+        }
+
+        // The image is safely written, so the checkpoint has served its
+        // purpose. There's none if the render finished before the first save,
+        // and a temporary one only if an earlier run was interrupted while
+        // saving.
+        if checkpointing {
+            let checkpoint = checkpoint_filename(&args[0], &options);
+            let _ = std::fs::remove_file(format!("{}.tmp", checkpoint));
+            let _ = std::fs::remove_file(checkpoint);
+        }
+
+        if let Some(reference) = &options.check_against {
+            if let Err((status, message)) = check_against(&name(reference), &pixels,
+                                                          bounds) {
+                eprintln!("{}: {}", program, message);
+                std::process::exit(status);
+            }
+        }
+    }
+
+    if animated_gif {
+        create_output(&args[0])
+            .and_then(|output| encode_animation(output, &animation, bounds,
+                                                options.fps.unwrap_or(DEFAULT_FPS)))
+            .expect("error writing GIF file");
+    }
+
+    if options.verbose {
+        eprintln!("total time: {:.2}s", program_start.elapsed().as_secs_f64());
+    }
 }
@@ -0,0 +1,93 @@
+//! Render a small image through the library's public interface, as another
+//! crate would.
+
+use mandelbrot::{render, render_parallel, render_pixels, write_image, Escape, Formula,
+                 Shading};
+use num::Complex;
+
+#[test]
+fn render_small_image() {
+    let bounds = (32, 32);
+    let pixels = render_pixels(bounds,
+                               Complex { re: -2.0, im: 1.0 },
+                               Complex { re: 1.0, im: -1.0 },
+                               &Formula::default(),
+                               &Shading::default());
+    assert_eq!(pixels.len(), 32 * 32);
+
+    // With the default shading, a point that escapes after `n` iterations is
+    // drawn as gray level `255 - n`, and points in the set are black.
+    let pixel = |column: usize, row: usize| pixels[row * bounds.0 + column];
+    assert_eq!(pixel(0, 0), 255 - 1);      // -2 + i
+    assert_eq!(pixel(16, 16), 0);          // -0.5, in the main cardioid
+    assert_eq!(pixel(8, 16), 0);           // -1.25, in the period-2 bulb
+    assert_eq!(pixel(8, 10), 255 - 14);    // -1.25 + 0.375i
+    assert_eq!(pixel(14, 10), 255 - 21);   // -0.6875 + 0.375i
+    assert_eq!(pixel(26, 10), 255 - 38);   // 0.4375 + 0.375i
+    assert_eq!(pixel(31, 31), 255 - 2);    // 0.90625 - 0.9375i
+
+    let filename = std::env::temp_dir()
+        .join(format!("mandelbrot-integration-{}.png", std::process::id()));
+    let filename = filename.to_str().unwrap();
+    write_image(filename, &pixels, bounds).unwrap();
+    std::fs::remove_file(filename).unwrap();
+}
+
+/// The reference image for `render_matches_golden_image`, relative to the
+/// crate's root.
+const GOLDEN_IMAGE: &str = "tests/golden/default-64x64.png";
+
+/// Render the whole set at the default settings and compare it pixel for
+/// pixel with a reference image, to catch any change in what the program
+/// draws, however it comes about.
+///
+/// If a change to the output is intended, run this test with the
+/// environment variable `MANDELBROT_UPDATE_GOLDEN` set to rewrite the
+/// reference image, and check the new image in along with the change.
+#[test]
+fn render_matches_golden_image() {
+    let bounds = (64, 64);
+    let pixels = render_pixels(bounds,
+                               Complex { re: -2.0, im: 1.5 },
+                               Complex { re: 1.0, im: -1.5 },
+                               &Formula::default(),
+                               &Shading::default());
+
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(GOLDEN_IMAGE);
+    if std::env::var_os("MANDELBROT_UPDATE_GOLDEN").is_some() {
+        write_image(path.to_str().unwrap(), &pixels, bounds).unwrap();
+        return;
+    }
+
+    let golden = image::open(&path)
+        .unwrap_or_else(|err| panic!("reading {}: {}", GOLDEN_IMAGE, err))
+        .to_luma();
+    assert_eq!(golden.dimensions(), (64, 64));
+    let differing = golden.into_raw().iter().zip(&pixels)
+        .filter(|(golden, rendered)| golden != rendered)
+        .count();
+    assert_eq!(differing, 0,
+               "{} pixels differ from {}; if that's intended, rerun with \
+                MANDELBROT_UPDATE_GOLDEN set to update it", differing, GOLDEN_IMAGE);
+}
+
+/// Rendering with one thread, as `--threads 1` does, or with several, or
+/// without rayon at all, must draw the same image: each pixel is computed on
+/// its own, so the order the work is done in can't matter.
+#[test]
+fn render_is_the_same_on_any_number_of_threads() {
+    let bounds = (48, 36);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+    let formula = Formula::default();
+
+    let mut sequential = vec![Escape::default(); bounds.0 * bounds.1];
+    render(&mut sequential, bounds, upper_left, lower_right, &formula);
+
+    for &threads in &[1, 4] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let parallel = pool.install(|| render_parallel(bounds, upper_left, lower_right,
+                                                       &formula));
+        assert!(parallel == sequential, "{} threads drew a different image", threads);
+    }
+}